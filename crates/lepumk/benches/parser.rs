@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use criterion::Throughput;
+
+use serde_json::json;
+
+use lepumk::ani::JsonParser;
+use lepumk::ani::ParserOptions;
+
+// about 50KB message, nested object and array of object
+fn message() -> String {
+	let items = (0..200)
+		.map(|i| {
+			json!({
+				"id": i,
+				"name": format!("item-{i}"),
+				"price": i as f64 * 1.5,
+				"tags": {"color": "red", "size": "xl", "on_sale": i % 2 == 0},
+				"attrs": {"weight": 12.5, "dims": {"w": 1, "h": 2, "d": 3}},
+				"desc": "a fairly long description used to make the payload bigger",
+				"remark": null
+			})
+		})
+		.collect::<Vec<_>>();
+
+	json!({
+		"trace_id": "4f90d13a42",
+		"ts": 1704164645123_i64,
+		"user": {"id": 7, "name": "foo", "address": {"city": "baz", "zip": "000000"}},
+		"items": items,
+		"ext": {"raw": "x".repeat(2048)}
+	})
+	.to_string()
+}
+
+fn parser(fold: HashSet<String>) -> JsonParser {
+	ParserOptions::fmt().with_sep("_").with_max_depth(10).with_fold(fold).init()
+}
+
+fn bench_run(c: &mut Criterion) {
+	let s = message();
+
+	let mut group = c.benchmark_group("json_parser");
+	group.throughput(Throughput::Bytes(s.len() as u64));
+
+	group.bench_function("serde_json_value", |b| {
+		b.iter(|| {
+			serde_json::from_str::<serde_json::Value>(black_box(&s)).unwrap()
+		})
+	});
+
+	let p = parser(HashSet::new());
+	group.bench_function("parse_str", |b| {
+		b.iter(|| p.parse_str(black_box(&s)).unwrap())
+	});
+
+	let p = parser(HashSet::from(["items".to_owned()]));
+	group.bench_function("parse_str_fold_items", |b| {
+		b.iter(|| p.parse_str(black_box(&s)).unwrap())
+	});
+
+	group.finish();
+}
+
+criterion_group!(benches, bench_run);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+use super::schema::TypeError;
+
+// error of parser json text into rows
+#[derive(Debug)]
+pub enum ParseError {
+	// input is not valid json or utf8
+	Json(serde_json::Error),
+	// nested depth is larger than max_depth
+	Depth { depth: i32, max_depth: i32 },
+	// value can not coerce into schema column
+	Type(TypeError),
+}
+
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ParseError::Json(err) => write!(f, "invalid json {err}"),
+			ParseError::Depth { depth, max_depth } => {
+				write!(
+					f,
+					"depth({depth}) is larger than max_depth({max_depth})"
+				)
+			}
+			ParseError::Type(err) => write!(f, "{err}"),
+		}
+	}
+}
+
+impl std::error::Error for ParseError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ParseError::Json(err) => Some(err),
+			ParseError::Depth { .. } => None,
+			ParseError::Type(err) => Some(err),
+		}
+	}
+}
+
+impl From<serde_json::Error> for ParseError {
+	fn from(err: serde_json::Error) -> Self {
+		ParseError::Json(err)
+	}
+}
+
+impl From<TypeError> for ParseError {
+	fn from(err: TypeError) -> Self {
+		ParseError::Type(err)
+	}
+}
+
+impl ParseError {
+	// keep the error type of async api, type error can downcast as TypeError
+	pub(crate) fn into_anyhow(self) -> anyhow::Error {
+		match self {
+			ParseError::Type(err) => err.into(),
+			other => other.into(),
+		}
+	}
+}
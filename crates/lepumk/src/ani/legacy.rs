@@ -0,0 +1,211 @@
+// flattener before the node tree, the reference of equivalence test
+// ported from the owned serde_json::Value version without logging
+
+use std::collections::HashMap;
+
+use serde_json::Map;
+use serde_json::Value;
+
+use super::ParserOptions;
+
+type Flat = HashMap<String, Value>;
+
+fn join_key(opt: &ParserOptions, pre_key: &str, curr_key: &str) -> String {
+	if pre_key.is_empty() {
+		curr_key.to_owned()
+	} else {
+		format!("{pre_key}{}{curr_key}", opt.get_sep())
+	}
+}
+
+pub fn run(opt: &ParserOptions, s: &str) -> anyhow::Result<Vec<Flat>> {
+	let val = serde_json::from_str::<Value>(s)?;
+	let key = join_key(opt, "", "");
+	if opt.strict_mode() && !opt.contain_key(&key) {
+		return Ok(vec![]);
+	}
+	if opt.contains_ignore(&key) {
+		return Ok(vec![]);
+	}
+	if opt.contains_fold(&key) {
+		let mut m = HashMap::new();
+		m.insert(key, val);
+		return Ok(vec![m]);
+	}
+
+	match val {
+		Value::Array(arr) => parser_array(opt, &arr, &key, &HashMap::new(), 0),
+		Value::Object(obj) => {
+			parser_object(opt, &obj, &key, &HashMap::new(), 0)
+		}
+		pri => {
+			let mut m = HashMap::new();
+			m.insert(key, pri);
+			Ok(vec![m])
+		}
+	}
+}
+
+fn parser_array(
+	opt: &ParserOptions,
+	arr: &[Value],
+	pre_key: &str,
+	curr: &Flat,
+	depth: i32,
+) -> anyhow::Result<Vec<Flat>> {
+	if depth > opt.max_depth && opt.max_depth > 0 {
+		anyhow::bail!(
+			"depth({}) is larger than max_depth({})",
+			depth,
+			opt.max_depth
+		)
+	}
+
+	let mut res = Vec::new();
+
+	let full_key = join_key(opt, pre_key, "");
+	if arr.is_empty() {
+		res.push(curr.clone());
+		let val = match opt.get_default(&full_key) {
+			Some(val) => val.clone(),
+			None => Value::Array(arr.to_vec()),
+		};
+		for item in res.iter_mut() {
+			item.insert(full_key.to_owned(), val.clone());
+		}
+		return Ok(res);
+	}
+
+	if opt.strict_mode() && !opt.contain_key(&full_key) {
+		return Ok(vec![curr.clone()]);
+	}
+	if opt.contains_ignore(&full_key) {
+		return Ok(vec![curr.clone()]);
+	}
+	if opt.contains_fold(&full_key) {
+		let mut data = curr.clone();
+		data.insert(full_key.to_owned(), Value::Array(arr.to_vec()));
+		return Ok(vec![data]);
+	}
+
+	for val in arr.iter() {
+		match val {
+			Value::Array(arr) => {
+				if let Ok(mut r) =
+					parser_array(opt, arr, &full_key, curr, depth + 1)
+				{
+					res.append(&mut r);
+				}
+			}
+			Value::Object(obj) => {
+				if let Ok(mut r) =
+					parser_object(opt, obj, &full_key, curr, depth + 1)
+				{
+					res.append(&mut r);
+				}
+			}
+			pri => {
+				let mut data = curr.clone();
+				let val = match opt.get_default(&full_key) {
+					Some(default_value) if pri.is_null() => {
+						default_value.clone()
+					}
+					_ => pri.clone(),
+				};
+				data.insert(full_key.clone(), val);
+				res.push(data);
+			}
+		}
+	}
+
+	Ok(res)
+}
+
+fn parser_object(
+	opt: &ParserOptions,
+	obj: &Map<String, Value>,
+	pre_key: &str,
+	curr: &Flat,
+	depth: i32,
+) -> anyhow::Result<Vec<Flat>> {
+	if depth > opt.max_depth && opt.max_depth > 0 {
+		anyhow::bail!(
+			"depth({}) is larger than max_depth({})",
+			depth,
+			opt.max_depth
+		)
+	}
+	let mut res: Vec<Flat> = vec![];
+	for (key, val) in obj.iter() {
+		let full_key = join_key(opt, pre_key, key);
+
+		if opt.strict_mode() && !opt.contain_key(&full_key) {
+			continue;
+		}
+		if opt.contains_ignore(&full_key) {
+			continue;
+		}
+
+		let fold = opt.contains_fold(&full_key);
+		match val {
+			Value::Array(arr) if !fold => {
+				if res.is_empty() {
+					res.push(curr.clone());
+				}
+				let mut temp_res = vec![];
+				for item in res.iter() {
+					if let Ok(mut r) =
+						parser_array(opt, arr, &full_key, item, depth + 1)
+					{
+						temp_res.append(&mut r);
+					}
+				}
+				res = temp_res;
+			}
+			Value::Object(obj) if !fold => {
+				if res.is_empty() {
+					res.push(curr.clone());
+				}
+				let mut temp_res = vec![];
+				for item in res.iter() {
+					if let Ok(mut r) =
+						parser_object(opt, obj, &full_key, item, depth + 1)
+					{
+						temp_res.append(&mut r);
+					}
+				}
+				res = temp_res;
+			}
+			// folded value or base value like bool, number, string and null
+			val => {
+				if res.is_empty() {
+					res.push(curr.clone());
+				}
+				let new_val = match opt.get_default(&full_key) {
+					Some(default_value) if val.is_null() => default_value,
+					_ => val,
+				};
+				for item in res.iter_mut() {
+					item.insert(full_key.to_owned(), new_val.clone());
+				}
+			}
+		}
+	}
+
+	if res.is_empty() {
+		res.push(curr.clone());
+	}
+
+	if obj.is_empty() {
+		let full_key = pre_key.to_owned();
+		for item in res.iter_mut() {
+			let val = match opt.get_default(&full_key) {
+				Some(val) => val.clone(),
+				None => Value::Object(obj.clone()),
+			};
+			item.insert(full_key.to_owned(), val);
+		}
+	}
+
+	Ok(res)
+}
@@ -1,5 +1,14 @@
+pub mod error;
+#[cfg(test)]
+mod legacy;
+pub mod node;
+pub mod schema;
+pub mod trace;
+
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::rc::Rc;
 use std::vec;
 
 use serde::Deserialize;
@@ -8,10 +17,18 @@ use serde_json::json;
 use serde_json::Map;
 
 use tracing::debug;
-use tracing::error;
 use tracing::info;
 use tracing::instrument;
 
+use error::ParseError;
+use node::Node;
+use trace::Decision;
+use trace::Rule;
+use trace::Tracer;
+
+// output row keep key insert order, object key keep source order
+pub type Row = indexmap::IndexMap<String, serde_json::Value>;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ParserOptions {
 	// filed join sep
@@ -29,6 +46,46 @@ pub struct ParserOptions {
 	strict: bool,
 	// keys from debug text
 	keys: HashSet<String>,
+	// typed output schema, coerce value after parser
+	schema: Option<schema::OutputSchema>,
+	// what to do with value nested deeper than max_depth
+	#[serde(default)]
+	depth_policy: DepthPolicy,
+}
+
+// what to do with value nested deeper than max_depth
+#[derive(
+	Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DepthPolicy {
+	// fail the whole message
+	#[default]
+	Fail,
+	// keep the subtree as json value at its key
+	Fold,
+	// drop the subtree and keep the other keys
+	Truncate,
+}
+
+// value nested deeper than max_depth which is fold or truncate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DepthExceeded {
+	// flattened key of the subtree
+	pub key: String,
+	pub depth: i32,
+}
+
+// parser result
+#[derive(Debug, Default)]
+pub struct Parsed {
+	pub rows: Vec<Row>,
+	// how many value coerce failed by output schema
+	pub failures: usize,
+	// subtree over max_depth, empty with policy fail
+	pub exceeded: Vec<DepthExceeded>,
+	// decision of every key, only in explain mode
+	pub trace: Vec<trace::Trace>,
 }
 
 impl ParserOptions {
@@ -85,6 +142,24 @@ impl ParserOptions {
 	pub fn get_keys(&self) -> &HashSet<String> {
 		&self.keys
 	}
+
+	// set output schema
+	pub fn with_schema(self, schema: Option<schema::OutputSchema>) -> Self {
+		Self { schema, ..self }
+	}
+
+	pub fn get_schema(&self) -> Option<&schema::OutputSchema> {
+		self.schema.as_ref()
+	}
+
+	// set depth policy
+	pub fn with_depth_policy(self, depth_policy: DepthPolicy) -> Self {
+		Self { depth_policy, ..self }
+	}
+
+	pub fn get_depth_policy(&self) -> DepthPolicy {
+		self.depth_policy
+	}
 }
 
 impl ParserOptions {
@@ -124,386 +199,459 @@ pub struct JsonParser(pub ParserOptions);
 
 impl JsonParser {
 	fn join_key(&self, pre_key: &str, curr_key: &str) -> String {
-		debug!(
-			"pre key {pre_key}, sep {}, curr_key {curr_key}",
-			self.0.get_sep()
-		);
-
 		if pre_key.is_empty() {
 			curr_key.to_owned()
 		} else {
-			format!("{pre_key}{}{curr_key}", self.0.get_sep())
+			let sep = self.0.get_sep();
+			let mut key = String::with_capacity(
+				pre_key.len() + sep.len() + curr_key.len(),
+			);
+			key.push_str(pre_key);
+			key.push_str(sep);
+			key.push_str(curr_key);
+			key
+		}
+	}
+
+	fn over_depth(&self, depth: i32) -> bool {
+		depth > self.0.max_depth && self.0.max_depth > 0
+	}
+
+	// apply depth policy on the subtree at key
+	// truncate return no row
+	fn exceed<'a>(
+		&self,
+		node: &'a Node<'a>,
+		key: &str,
+		depth: i32,
+		walk: &mut Walk,
+	) -> Result<Vec<Partial<'a>>, ParseError> {
+		let rows = match self.0.depth_policy {
+			DepthPolicy::Fail => {
+				return Err(ParseError::Depth {
+					depth,
+					max_depth: self.0.max_depth,
+				});
+			}
+			DepthPolicy::Fold => {
+				walk.tracer.record(key, Decision::Fold, Some(Rule::Depth));
+				vec![Partial::single(key.to_owned(), Leaf::Node(node))]
+			}
+			DepthPolicy::Truncate => {
+				walk.tracer.record(key, Decision::Drop, Some(Rule::Depth));
+				vec![]
+			}
+		};
+		walk.exceeded.push(DepthExceeded { key: key.to_owned(), depth });
+		Ok(rows)
+	}
+
+	// use default value if value is null
+	fn leaf<'a>(&'a self, key: &str, val: &'a Node<'a>) -> Leaf<'a> {
+		if val.is_null() {
+			if let Some(default_value) = self.0.get_default(key) {
+				return Leaf::Value(default_value);
+			}
+		}
+		Leaf::Node(val)
+	}
+
+	// output primitive value or its default
+	fn keep<'a>(
+		&'a self,
+		key: &str,
+		val: &'a Node<'a>,
+		walk: &mut Walk,
+	) -> Leaf<'a> {
+		let leaf = self.leaf(key, val);
+		match leaf {
+			Leaf::Node(_) => walk.tracer.record(key, Decision::Keep, None),
+			Leaf::Value(_) => {
+				walk.tracer.record(key, Decision::Default, Some(Rule::Default))
+			}
+		}
+		leaf
+	}
+
+	// empty array or object use default value or itself
+	fn empty<'a>(
+		&'a self,
+		key: &str,
+		val: &'a Node<'a>,
+		walk: &mut Walk,
+	) -> Leaf<'a> {
+		match self.0.get_default(key) {
+			Some(val) => {
+				walk.tracer.record(key, Decision::Default, Some(Rule::Default));
+				Leaf::Value(val)
+			}
+			None => {
+				walk.tracer.record(key, Decision::Keep, None);
+				Leaf::Node(val)
+			}
+		}
+	}
+}
+
+// state collect while flatten one message
+#[derive(Debug, Default)]
+struct Walk {
+	exceeded: Vec<DepthExceeded>,
+	tracer: Tracer,
+}
+
+// value of flatten key, copy into row only when the row build
+#[derive(Debug, Clone, Copy)]
+enum Leaf<'a> {
+	Node(&'a Node<'a>),
+	Value(&'a serde_json::Value),
+}
+
+impl Leaf<'_> {
+	fn to_value(self) -> serde_json::Value {
+		match self {
+			Leaf::Node(node) => node.to_value(),
+			Leaf::Value(val) => val.clone(),
+		}
+	}
+}
+
+// key values shared by every row expand from the same prefix
+type Chunk<'a> = Rc<Vec<(String, Leaf<'a>)>>;
+
+// a row under build, row from the same prefix share chunk
+// instead of clone the whole row every level
+#[derive(Debug, Clone, Default)]
+struct Partial<'a>(Vec<Chunk<'a>>);
+
+impl<'a> Partial<'a> {
+	fn single(key: String, val: Leaf<'a>) -> Self {
+		Self(vec![Rc::new(vec![(key, val)])])
+	}
+
+	fn to_row(&self) -> Row {
+		let len = self.0.iter().map(|chunk| chunk.len()).sum();
+		let mut row = Row::with_capacity(len);
+		for chunk in self.0.iter() {
+			for (key, val) in chunk.iter() {
+				row.insert(key.clone(), val.to_value());
+			}
+		}
+		row
+	}
+}
+
+// append pending key values into every row
+fn flush<'a>(
+	rows: &mut Vec<Partial<'a>>,
+	pending: &mut Vec<(String, Leaf<'a>)>,
+) {
+	if pending.is_empty() {
+		return;
+	}
+	if rows.is_empty() {
+		rows.push(Partial::default());
+	}
+	let chunk = Rc::new(std::mem::take(pending));
+	for row in rows.iter_mut() {
+		row.0.push(chunk.clone());
+	}
+}
+
+// every row join every sub row
+fn product<'a>(
+	rows: Vec<Partial<'a>>,
+	sub: Vec<Partial<'a>>,
+) -> Vec<Partial<'a>> {
+	if sub.len() == 1 {
+		let mut rows = rows;
+		for row in rows.iter_mut() {
+			row.0.extend(sub[0].0.iter().cloned());
 		}
+		return rows;
 	}
+
+	let mut res = Vec::with_capacity(rows.len() * sub.len());
+	for row in rows.iter() {
+		for item in sub.iter() {
+			let mut data =
+				Partial(Vec::with_capacity(row.0.len() + item.0.len()));
+			data.0.extend(row.0.iter().cloned());
+			data.0.extend(item.0.iter().cloned());
+			res.push(data);
+		}
+	}
+	res
 }
 
 impl JsonParser {
-	#[tracing::instrument(skip(self, s))]
-	pub async fn run(
+	// parser json text and then coerce by output schema
+	pub fn parse_str(&self, s: &str) -> Result<Parsed, ParseError> {
+		let val = Node::parse(s)?;
+		self.parse_node(&val, Walk::default())
+	}
+
+	// parser json text and record the decision of every key in trace
+	pub fn explain_str(&self, s: &str) -> Result<Parsed, ParseError> {
+		let val = Node::parse(s)?;
+		let walk = Walk { tracer: Tracer::enable(), ..Walk::default() };
+		self.parse_node(&val, walk)
+	}
+
+	// parser json bytes, bytes must be utf8
+	pub fn parse_bytes(&self, b: &[u8]) -> Result<Parsed, ParseError> {
+		let val: Node = serde_json::from_slice(b)?;
+		self.parse_node(&val, Walk::default())
+	}
+
+	// parser a decoded json value
+	pub fn parse_value(
 		&self,
-		s: &str,
-	) -> anyhow::Result<Vec<HashMap<String, serde_json::Value>>> {
-		debug!("parser value {}", s);
+		val: &serde_json::Value,
+	) -> Result<Parsed, ParseError> {
+		self.parse_node(&Node::from(val), Walk::default())
+	}
 
-		let val = serde_json::from_str::<serde_json::Value>(s)?;
+	fn parse_node(
+		&self,
+		val: &Node,
+		mut walk: Walk,
+	) -> Result<Parsed, ParseError> {
+		let rows = self.flatten(val, &mut walk)?;
+		let (rows, failures) = match self.0.get_schema() {
+			Some(schema) => {
+				let coerced = schema.coerce(rows)?;
+				(coerced.rows, coerced.failures)
+			}
+			None => (rows, 0),
+		};
+		Ok(Parsed {
+			rows,
+			failures,
+			exceeded: walk.exceeded,
+			trace: walk.tracer.finish(),
+		})
+	}
+
+	// async version of parse_str
+	// coerce failed with on_error fail return schema::TypeError
+	pub async fn run_typed(&self, s: &str) -> anyhow::Result<Parsed> {
+		self.parse_str(s).map_err(ParseError::into_anyhow)
+	}
+
+	pub async fn run(&self, s: &str) -> anyhow::Result<Vec<Row>> {
+		Ok(self.run_typed(s).await?.rows)
+	}
+
+	fn flatten(
+		&self,
+		val: &Node,
+		walk: &mut Walk,
+	) -> Result<Vec<Row>, ParseError> {
 		let key = self.join_key("", "");
 		// check in strict or not
 		if self.0.strict_mode() && !self.0.contain_key(&key) {
-			debug!(
-				"strict mode or not  {} key is {} {}",
-				self.0.strict_mode(),
-				key,
-				self.0.contain_key(&key)
-			);
-
+			walk.tracer.record(&key, Decision::Drop, Some(Rule::Strict));
 			return Ok(vec![]);
 		}
 		// check key is ignore
 		if self.0.contains_ignore(&key) {
-			debug!("expected key {} in keys", key);
-			//
+			walk.tracer.record(&key, Decision::Drop, Some(Rule::Ignore));
 			return Ok(vec![]);
 		}
 
 		// check is key is fold
 		if self.0.contains_fold(&key) {
-			let mut m = HashMap::new();
-			m.insert(key, val);
+			walk.tracer.record(&key, Decision::Fold, Some(Rule::Fold));
+			let mut m = Row::new();
+			m.insert(key, val.to_value());
 			return Ok(vec![m]);
 		}
 
-		// strict mode but not contain key
-		if self.0.strict_mode() && !self.0.contain_key(&key) {
-			debug!("run in strict mode but not found key {} in keys", key);
-			return Ok(vec![]);
-		}
-
-		match val {
-			serde_json::Value::Array(arr) => {
-				let m: HashMap<String, serde_json::Value> = HashMap::new();
-
-				self.parser_array(&arr, &key, &m, 0)
-			}
-
-			serde_json::Value::Object(obj) => {
-				let m: HashMap<String, serde_json::Value> = HashMap::new();
-				self.parser_object(&obj, &key, &m, 0)
-			}
-
+		let rows = match val {
+			Node::Array(arr) => self.parser_array(val, arr, &key, 0, walk)?,
+			Node::Object(obj) => self.parser_object(val, obj, &key, 0, walk)?,
 			pri => {
-				let mut m = HashMap::new();
-				m.insert(key, pri);
-				Ok(vec![m])
+				walk.tracer.record(&key, Decision::Keep, None);
+				let mut m = Row::new();
+				m.insert(key, pri.to_value());
+				return Ok(vec![m]);
 			}
-		}
+		};
+
+		Ok(rows.iter().map(Partial::to_row).collect())
 	}
 
-	#[allow(clippy::ptr_arg)]
-	#[instrument(skip(self, arr, pre_key, depth, curr))]
-	fn parser_array(
-		&self,
-		arr: &Vec<serde_json::Value>,
+	// flatten array, every item expand as rows
+	fn parser_array<'a>(
+		&'a self,
+		node: &'a Node<'a>,
+		arr: &'a [Node<'a>],
 		pre_key: &str,
-		curr: &HashMap<String, serde_json::Value>,
 		depth: i32,
-	) -> anyhow::Result<Vec<HashMap<String, serde_json::Value>>> {
-		//
-		if depth > self.0.max_depth && self.0.max_depth > 0 {
-			anyhow::bail!(
-				"depth({}) is larger than max_depth({})",
-				depth,
-				self.0.max_depth
-			)
+		walk: &mut Walk,
+	) -> Result<Vec<Partial<'a>>, ParseError> {
+		if self.over_depth(depth) {
+			return self.exceed(node, pre_key, depth, walk);
 		}
 
-		let mut res = Vec::new();
-
 		let full_key = self.join_key(pre_key, "");
 		if arr.is_empty() {
-			debug!("parser array but empty");
-			res.push(curr.clone());
-			let val = if let Some(val) = self.0.get_default(&full_key) {
-				debug!("empty array use default value {:?}", val);
-				val.clone()
-			} else {
-				debug!("empty array use []");
-				serde_json::Value::Array(arr.clone())
-			};
-
-			for item in res.iter_mut() {
-				item.insert(full_key.to_owned(), val.clone());
-			}
-			return Ok(res);
+			// empty array use default value or []
+			let val = self.empty(&full_key, node, walk);
+			return Ok(vec![Partial::single(full_key, val)]);
 		}
 
+		walk.tracer.record(pre_key, Decision::Expand, None);
+
 		if self.0.strict_mode() && !self.0.contain_key(&full_key) {
-			debug!("run in strict mode not found key {}", full_key);
-			return Ok(vec![curr.clone()]);
+			walk.tracer.record(&full_key, Decision::Drop, Some(Rule::Strict));
+			return Ok(vec![Partial::default()]);
 		}
 
 		if self.0.contains_ignore(&full_key) {
-			debug!("ignore key {}", full_key);
-			return Ok(vec![curr.clone()]);
+			walk.tracer.record(&full_key, Decision::Drop, Some(Rule::Ignore));
+			return Ok(vec![Partial::default()]);
 		}
 
 		if self.0.contains_fold(&full_key) {
-			debug!("fold key {}", full_key);
-			let mut data = curr.clone();
-			data.insert(
-				full_key.to_owned(),
-				serde_json::Value::Array(arr.clone()),
-			);
-			return Ok(vec![data]);
+			walk.tracer.record(&full_key, Decision::Fold, Some(Rule::Fold));
+			return Ok(vec![Partial::single(full_key, Leaf::Node(node))]);
 		}
 
+		let mut res = Vec::with_capacity(arr.len());
 		for val in arr.iter() {
-			if self.0.contains_fold(&full_key) {
-				let mut data = curr.clone();
-				data.insert(full_key.to_owned(), val.clone());
-				res.push(data);
-				continue;
-			}
-
-			match val {
-				serde_json::Value::Array(arr) => {
-					debug!("parser occur arr {:?}", arr);
-					match self.parser_array(arr, &full_key, curr, depth + 1) {
-						Ok(mut r) => {
-							// todo
-							res.append(&mut r);
-						}
-						Err(err) => {
-							//
-							error!("parser array error {:?}", err);
-						}
-					}
+			let sub = match val {
+				Node::Array(sub_arr) => {
+					self.parser_array(val, sub_arr, &full_key, depth + 1, walk)?
 				}
-				serde_json::Value::Object(obj) => {
-					debug!("parser occur obj {:?}", obj);
-					match self.parser_object(obj, &full_key, curr, depth + 1) {
-						Ok(mut r) => {
-							res.append(&mut r);
-						}
-						Err(err) => {
-							error!("parser array error {:?}", err);
-						}
-					}
+				Node::Object(obj) => {
+					self.parser_object(val, obj, &full_key, depth + 1, walk)?
 				}
-				//
 				pri => {
-					debug!("value {:?}", pri);
-					if pri.is_null() {
-						if let Some(default_value) =
-							self.0.get_default(&full_key)
-						{
-							let mut data = curr.clone();
-							data.insert(
-								full_key.clone(),
-								default_value.clone(),
-							);
-							res.push(data);
-							continue;
-						}
-					}
-
-					let mut data = curr.clone();
-					data.insert(full_key.to_owned(), pri.clone());
-					res.push(data);
+					let leaf = self.keep(&full_key, pri, walk);
+					res.push(Partial::single(full_key.clone(), leaf));
+					continue;
 				}
-			}
+			};
+
+			res.extend(sub);
+		}
+
+		// every item is truncated
+		if res.is_empty() {
+			res.push(Partial::default());
 		}
 
 		Ok(res)
 	}
 
-	#[instrument(skip(self, obj, curr, depth, pre_key))]
-	fn parser_object(
-		&self,
-		obj: &Map<String, serde_json::Value>,
+	// flatten object, array value in object expand the rows
+	fn parser_object<'a>(
+		&'a self,
+		node: &'a Node<'a>,
+		obj: &'a [(Cow<'a, str>, Node<'a>)],
 		pre_key: &str,
-		curr: &HashMap<String, serde_json::Value>,
 		depth: i32,
-	) -> anyhow::Result<Vec<HashMap<String, serde_json::Value>>> {
-		//...
-		debug!("obj {}", json!(obj).to_string());
-		//...
-		if depth > self.0.max_depth && self.0.max_depth > 0 {
-			error!(
-				"depth({}) is larger than max_depth({})",
-				depth, self.0.max_depth
-			);
-			anyhow::bail!(
-				"depth({}) is larger than max_depth({})",
-				depth,
-				self.0.max_depth
-			)
+		walk: &mut Walk,
+	) -> Result<Vec<Partial<'a>>, ParseError> {
+		if self.over_depth(depth) {
+			return self.exceed(node, pre_key, depth, walk);
 		}
-		let mut res: Vec<HashMap<String, serde_json::Value>> = vec![];
+
+		if !obj.is_empty() {
+			walk.tracer.record(pre_key, Decision::Expand, None);
+		}
+
+		let mut res: Vec<Partial<'a>> = vec![];
+		// value append into every row
+		let mut pending = vec![];
 		//  iter map  and then check value type
 		for (key, val) in obj.iter() {
 			let full_key = self.join_key(pre_key, key);
 
 			// if is strict mode and keys not contains key
 			if self.0.strict_mode() && !self.0.contain_key(&full_key) {
-				debug!("run in strict mode occur key {}", full_key);
+				walk.tracer.record(
+					&full_key,
+					Decision::Drop,
+					Some(Rule::Strict),
+				);
 				continue;
 			}
 
 			// if this key is ignore will drop this key and value
 			if self.0.contains_ignore(&full_key) {
-				debug!("ignore full key {}", full_key);
+				walk.tracer.record(
+					&full_key,
+					Decision::Drop,
+					Some(Rule::Ignore),
+				);
 				continue;
 			}
 
 			// if fold this value will not expand
 			if self.0.contains_fold(&full_key) {
-				debug!("full_key {} fold", full_key);
-				if res.is_empty() {
-					res.push(curr.clone());
-				}
-				//  check val is null
-				let new_val = if val.is_null() {
-					match self.0.get_default(&full_key) {
-						Some(default_value) => {
-							debug!(
-								"default value  full key {} default value {:?}",
-								full_key, default_value
-							);
-							// default value
-							default_value
-						}
-						None => {
-							// return null
-							debug!("occur null value but not set in default_values {}", full_key);
-							val
-						}
-					}
-				} else {
-					val
-				};
-
-				// iter result vec and push key value into item
-				for item in res.iter_mut() {
-					item.insert(full_key.to_owned(), new_val.clone());
-				}
-
+				walk.tracer.record(&full_key, Decision::Fold, Some(Rule::Fold));
+				pending.push((full_key.to_owned(), self.leaf(&full_key, val)));
 				continue;
 			}
 
-			match val {
-				serde_json::Value::Array(arr) => {
-					if res.is_empty() {
-						res.push(curr.clone());
-					}
-					let mut temp_res: Vec<HashMap<String, serde_json::Value>> =
-						vec![];
-
-					for item in res.iter() {
-						let rr =
-							self.parser_array(arr, &full_key, item, depth + 1);
-
-						match rr {
-							Ok(mut r) => {
-								temp_res.append(&mut r);
-							}
-							Err(err) => {
-								error!("parser array error {:?}", err);
-							}
-						}
-					}
-
-					res = temp_res;
+			let sub = match val {
+				Node::Array(arr) => {
+					self.parser_array(val, arr, &full_key, depth + 1, walk)?
 				}
-				serde_json::Value::Object(obj) => {
-					// check is empty
-					if res.is_empty() {
-						res.push(curr.clone());
-					}
-
-					let mut temp_res = vec![];
-
-					for item in res.iter() {
-						let rr =
-							self.parser_object(obj, &full_key, item, depth + 1);
-						match rr {
-							Ok(mut r) => {
-								// extend array list
-								temp_res.append(&mut r);
-							}
-
-							Err(err) => {
-								// occur error
-								error!(
-									"parser error {} {:?}",
-									json!(obj).to_string(),
-									err
-								);
-							}
-						};
-					}
-					res = temp_res;
+				Node::Object(obj) => {
+					self.parser_object(val, obj, &full_key, depth + 1, walk)?
 				}
-
 				pri => {
 					// base value type like bool, number, string and null
-					if res.is_empty() {
-						res.push(curr.clone());
-					}
-					//  check val is null
-					let new_val = if pri.is_null() {
-						match self.0.get_default(&full_key) {
-							Some(default_value) => {
-								debug!(
-									"default value  full key {} default value {:?}",
-									full_key, default_value
-								);
-								// default value
-								default_value
-							}
-							None => {
-								// return null
-								pri
-							}
-						}
-					} else {
-						pri
-					};
-
-					for item in res.iter_mut() {
-						item.insert(full_key.to_owned(), new_val.clone());
-					}
+					let leaf = self.keep(&full_key, pri, walk);
+					pending.push((full_key, leaf));
 					continue;
 				}
+			};
+
+			// truncated subtree add nothing
+			if sub.is_empty() {
+				continue;
 			}
-		}
 
-		// check res is empty if return [curr.clone()]
-		if res.is_empty() {
-			res.push(curr.clone());
+			if res.is_empty() {
+				res.push(Partial::default());
+			}
+			flush(&mut res, &mut pending);
+			res = product(res, sub);
 		}
 
 		if obj.is_empty() {
-			let full_key = pre_key.to_owned();
-			for item in res.iter_mut() {
-				let val = if let Some(val) = self.0.get_default(&full_key) {
-					val.clone()
-				} else {
-					serde_json::Value::Object(obj.clone())
-				};
+			// empty object use default value or {}
+			let val = self.empty(pre_key, &EMPTY_OBJECT, walk);
+			pending.push((pre_key.to_owned(), val));
+		}
+		flush(&mut res, &mut pending);
 
-				item.insert(full_key.to_owned(), val);
-			}
+		// check res is empty if return [curr.clone()]
+		if res.is_empty() {
+			res.push(Partial::default());
 		}
 
 		Ok(res)
 	}
 }
 
+static EMPTY_OBJECT: Node<'static> = Node::Object(vec![]);
+
 // parser input property
 // map/array/string,bool,number,null
 impl JsonParser {
-	#[instrument(skip(self, s))]
 	pub async fn property(&self, s: &str) -> anyhow::Result<Property> {
+		Ok(self.property_str(s)?)
+	}
+
+	// detect property tree of json text
+	#[instrument(skip(self, s))]
+	pub fn property_str(&self, s: &str) -> Result<Property, ParseError> {
 		debug!("input value {}", s);
 		let val = serde_json::from_str::<serde_json::Value>(s)?;
 
@@ -743,6 +891,7 @@ impl Property {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::to_string_in_format_args)]
 mod tests {
 	use serde_json::json;
 	use std::collections::HashMap;
@@ -755,6 +904,11 @@ mod tests {
 	use tracing_subscriber::fmt::format::FmtSpan;
 	use tracing_subscriber::fmt::time::LocalTime;
 
+	use crate::ani::error::ParseError;
+	use crate::ani::legacy;
+	use crate::ani::schema::OutputSchema;
+	use crate::ani::schema::TypeError;
+	use crate::ani::DepthPolicy;
 	use crate::ani::ParserOptions;
 	use crate::ani::PropertyItem;
 
@@ -1085,6 +1239,105 @@ mod tests {
 		Ok(())
 	}
 
+	#[tokio::test]
+	async fn test_parser_schema() -> anyhow::Result<()> {
+		let _ = _Str.clone();
+
+		let schema = serde_json::from_value::<OutputSchema>(json!({
+			"columns": [
+				{"name": "a_id", "type": "int", "on_error": "fail"},
+				{"name": "a_ok", "type": "bool", "on_error": "null"}
+			]
+		}))?;
+		let p =
+			ParserOptions::fmt().with_sep("_").with_schema(Some(schema)).init();
+
+		let res = p.run_typed(r#"{"a": {"id": "12", "ok": "maybe"}}"#).await?;
+		assert_eq!(res.failures, 1);
+		assert_eq!(res.rows[0].get("a_id"), Some(&json!(12)));
+		assert_eq!(res.rows[0].get("a_ok"), Some(&serde_json::Value::Null));
+
+		let err = p.run(r#"{"a": {"id": "x"}}"#).await.unwrap_err();
+		assert!(err.downcast_ref::<TypeError>().is_some());
+		Ok(())
+	}
+
+	#[test]
+	fn test_explain() {
+		let s = r#"{"a": 1, "b": null, "c": {"d": [1, 2], "e": {"f": 1}}, "g": 2, "h": [{"i": [1]}]}"#;
+		let p = ParserOptions::fmt()
+			.with_sep("_")
+			.with_max_depth(2)
+			.with_depth_policy(DepthPolicy::Truncate)
+			.with_ignore(HashSet::from(["g".to_owned()]))
+			.with_fold(HashSet::from(["c_e".to_owned()]))
+			.with_default_value(HashMap::from([("b".to_owned(), json!(0))]))
+			.init();
+
+		let res = p.explain_str(s).unwrap();
+		assert_eq!(
+			json!(res.trace),
+			json!([
+				{"key": "", "decision": "expand"},
+				{"key": "a", "decision": "keep"},
+				{"key": "b", "decision": "default", "rule": "default"},
+				{"key": "c", "decision": "expand"},
+				{"key": "c_d", "decision": "expand"},
+				{"key": "c_d_", "decision": "keep"},
+				{"key": "c_e", "decision": "fold", "rule": "fold"},
+				{"key": "g", "decision": "drop", "rule": "ignore"},
+				{"key": "h", "decision": "expand"},
+				{"key": "h_", "decision": "expand"},
+				{"key": "h__i", "decision": "drop", "rule": "depth"}
+			])
+		);
+		assert_eq!(json!(res.rows), json!(p.parse_str(s).unwrap().rows));
+		assert!(p.parse_str(s).unwrap().trace.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_parser_order() -> anyhow::Result<()> {
+		let _ = _Str.clone();
+
+		let p = ParserOptions::fmt().with_sep("_").init();
+		let s = r#"{"b": 1, "a": {"z": 1, "y": [1, 2]}, "c": null}"#;
+
+		let res = p.run(s).await?;
+		assert_eq!(res.len(), 2);
+		for row in res.iter() {
+			let keys = row.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+			assert_eq!(keys, vec!["b", "a_z", "a_y_", "c"]);
+		}
+		assert_eq!(
+			json!(res).to_string(),
+			r#"[{"b":1,"a_z":1,"a_y_":1,"c":null},{"b":1,"a_z":1,"a_y_":2,"c":null}]"#
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_sync() {
+		let p = ParserOptions::fmt().with_sep("_").with_max_depth(2).init();
+
+		// no tokio runtime in plain thread
+		let res = std::thread::spawn(move || {
+			let a = p.parse_str(r#"{"a": {"b": [1, 2]}}"#).unwrap();
+			let b = p.parse_bytes(br#"{"a": {"b": [1, 2]}}"#).unwrap();
+			let c = p.parse_value(&json!({"a": {"b": [1, 2]}})).unwrap();
+			assert_eq!(json!(a.rows), json!(b.rows));
+			assert_eq!(json!(a.rows), json!(c.rows));
+
+			let err = p.parse_str(r#"{"a": "#).unwrap_err();
+			assert!(matches!(err, ParseError::Json(_)));
+			let err = p.parse_bytes(b"\"\xff\"").unwrap_err();
+			assert!(matches!(err, ParseError::Json(_)));
+			a.rows
+		})
+		.join()
+		.unwrap();
+		assert_eq!(res.len(), 2);
+	}
+
 	#[tokio::test]
 	async fn test_max_depth() -> anyhow::Result<()> {
 		let _ = _Str.clone();
@@ -1115,4 +1368,151 @@ mod tests {
 		}
 		Ok(())
 	}
+
+	#[tokio::test]
+	async fn test_depth_policy() -> anyhow::Result<()> {
+		let _ = _Str.clone();
+
+		let s = r#"{
+        "key":"value"
+        ,"complex_array": [
+            {
+                "number":1
+                , "nest":[1,2]
+                , "nest_obj": {"key":"value", "nest_arr":[1,2,3]}
+            }
+         ]
+      }"#;
+		let opt = ParserOptions::fmt().with_max_depth(2).with_sep("_");
+
+		// nested and top level violation fail the message the same way
+		let p = opt.clone().init();
+		let err = p.parse_str(s).unwrap_err();
+		assert!(matches!(err, ParseError::Depth { depth: 3, max_depth: 2 }));
+		let p = ParserOptions::fmt().with_max_depth(1).with_sep("_").init();
+		let err = p.parse_str(s).unwrap_err();
+		assert!(matches!(err, ParseError::Depth { depth: 2, max_depth: 1 }));
+
+		let p = opt.clone().with_depth_policy(DepthPolicy::Fold).init();
+		let res = p.parse_str(s)?;
+		assert_eq!(
+			json!(res.rows).to_string(),
+			r#"[{"key":"value","complex_array__number":1,"complex_array__nest":[1,2],"complex_array__nest_obj":{"key":"value","nest_arr":[1,2,3]}}]"#
+		);
+		assert_eq!(res.exceeded.len(), 2);
+		assert_eq!(res.exceeded[0].key, "complex_array__nest");
+
+		let p = opt.with_depth_policy(DepthPolicy::Truncate).init();
+		let res = p.parse_str(s)?;
+		assert_eq!(
+			json!(res.rows).to_string(),
+			r#"[{"key":"value","complex_array__number":1}]"#
+		);
+		assert_eq!(res.exceeded.len(), 2);
+		Ok(())
+	}
+
+	// xorshift, the corpus is the same on every run
+	struct Corpus(u64);
+
+	impl Corpus {
+		fn next(&mut self, n: u64) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0 % n
+		}
+
+		fn value(&mut self, depth: u32) -> serde_json::Value {
+			let kind = if depth >= 4 { self.next(4) } else { self.next(7) };
+			match kind {
+				0 => serde_json::Value::Null,
+				1 => json!(self.next(2) == 0),
+				2 => json!(self.next(100)),
+				3 => json!(format!("s{}", self.next(10))),
+				4 | 5 => {
+					let mut obj = serde_json::Map::new();
+					for _ in 0..self.next(4) {
+						let key = ["a", "b", "c", "d"][self.next(4) as usize];
+						obj.insert(key.to_owned(), self.value(depth + 1));
+					}
+					serde_json::Value::Object(obj)
+				}
+				_ => (0..self.next(4)).map(|_| self.value(depth + 1)).collect(),
+			}
+		}
+
+		// some of the keys
+		fn pick(&mut self, keys: &HashSet<String>, n: u64) -> HashSet<String> {
+			keys.iter().filter(|_| self.next(n) == 0).cloned().collect()
+		}
+	}
+
+	// every full key of value, the same join as the parser
+	fn full_keys(
+		val: &serde_json::Value,
+		pre: &str,
+		sep: &str,
+		keys: &mut HashSet<String>,
+	) {
+		let join = |key: &str| {
+			if pre.is_empty() {
+				key.to_owned()
+			} else {
+				format!("{pre}{sep}{key}")
+			}
+		};
+		keys.insert(pre.to_owned());
+		match val {
+			serde_json::Value::Object(obj) => {
+				for (key, val) in obj {
+					full_keys(val, &join(key), sep, keys);
+				}
+			}
+			serde_json::Value::Array(arr) => {
+				for val in arr {
+					full_keys(val, &join(""), sep, keys);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	#[test]
+	fn test_parser_legacy() -> anyhow::Result<()> {
+		let mut corpus = Corpus(0x9e3779b97f4a7c15);
+		for _ in 0..20000 {
+			let val = corpus.value(0);
+			let sep = ["_", "."][corpus.next(2) as usize];
+			let mut keys = HashSet::new();
+			full_keys(&val, "", sep, &mut keys);
+
+			let strict = corpus.next(4) == 0;
+			let default_value = corpus
+				.pick(&keys, 4)
+				.into_iter()
+				.map(|k| (k, json!("default")))
+				.collect();
+			let opt = ParserOptions::fmt()
+				.with_sep(sep)
+				.with_fold(corpus.pick(&keys, 6))
+				.with_ignore(corpus.pick(&keys, 6))
+				.with_default_value(default_value)
+				.with_strict_mode(strict)
+				.with_keys(if strict {
+					corpus.pick(&keys, 2)
+				} else {
+					HashSet::new()
+				});
+
+			let s = val.to_string();
+			let want = legacy::run(&opt, &s)?;
+			let got = opt.clone().init().parse_str(&s)?.rows;
+			// rows of the legacy flattener are not ordered
+			let got: Vec<HashMap<String, serde_json::Value>> =
+				got.into_iter().map(|row| row.into_iter().collect()).collect();
+			assert_eq!(got, want, "{} {:?}", s, opt);
+		}
+		Ok(())
+	}
 }
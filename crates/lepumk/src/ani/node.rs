@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::Deserializer;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserialize;
+
+// object with key less than this use linear scan to check duplicate key
+const SCAN_KEYS: usize = 16;
+
+// json tree which borrow string and key from input text if it can
+// object keep key in source order, duplicate key keep the first position
+// and the last value like serde_json::Value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<'a> {
+	Null,
+	Bool(bool),
+	Number(serde_json::Number),
+	String(Cow<'a, str>),
+	Array(Vec<Node<'a>>),
+	Object(Vec<(Cow<'a, str>, Node<'a>)>),
+}
+
+impl<'a> Node<'a> {
+	// parser text without copy string that has no escape
+	pub fn parse(s: &'a str) -> serde_json::Result<Node<'a>> {
+		serde_json::from_str(s)
+	}
+
+	pub fn is_null(&self) -> bool {
+		matches!(self, Node::Null)
+	}
+
+	// build owned value
+	pub fn to_value(&self) -> serde_json::Value {
+		match self {
+			Node::Null => serde_json::Value::Null,
+			Node::Bool(b) => serde_json::Value::Bool(*b),
+			Node::Number(n) => serde_json::Value::Number(n.clone()),
+			Node::String(s) => serde_json::Value::String(s.to_string()),
+			Node::Array(arr) => serde_json::Value::Array(
+				arr.iter().map(Node::to_value).collect(),
+			),
+			Node::Object(obj) => {
+				let mut m = serde_json::Map::with_capacity(obj.len());
+				for (key, val) in obj.iter() {
+					m.insert(key.to_string(), val.to_value());
+				}
+				serde_json::Value::Object(m)
+			}
+		}
+	}
+}
+
+// borrow a parsed value
+impl<'a> From<&'a serde_json::Value> for Node<'a> {
+	fn from(val: &'a serde_json::Value) -> Self {
+		match val {
+			serde_json::Value::Null => Node::Null,
+			serde_json::Value::Bool(b) => Node::Bool(*b),
+			serde_json::Value::Number(n) => Node::Number(n.clone()),
+			serde_json::Value::String(s) => Node::String(Cow::Borrowed(s)),
+			serde_json::Value::Array(arr) => {
+				Node::Array(arr.iter().map(Node::from).collect())
+			}
+			serde_json::Value::Object(obj) => Node::Object(
+				obj.iter()
+					.map(|(k, v)| (Cow::Borrowed(k.as_str()), Node::from(v)))
+					.collect(),
+			),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for Node<'de> {
+	fn deserialize<D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Self, D::Error> {
+		deserializer.deserialize_any(NodeVisitor)
+	}
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+	type Value = Node<'de>;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("any valid JSON value")
+	}
+
+	fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+		Ok(Node::Bool(v))
+	}
+
+	fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+		Ok(Node::Number(v.into()))
+	}
+
+	fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+		Ok(Node::Number(v.into()))
+	}
+
+	fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+		Ok(serde_json::Number::from_f64(v).map_or(Node::Null, Node::Number))
+	}
+
+	fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+		Ok(Node::String(Cow::Borrowed(v)))
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+		Ok(Node::String(Cow::Owned(v.to_owned())))
+	}
+
+	fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+		Ok(Node::String(Cow::Owned(v)))
+	}
+
+	fn visit_none<E>(self) -> Result<Self::Value, E> {
+		Ok(Node::Null)
+	}
+
+	fn visit_unit<E>(self) -> Result<Self::Value, E> {
+		Ok(Node::Null)
+	}
+
+	fn visit_some<D: Deserializer<'de>>(
+		self,
+		deserializer: D,
+	) -> Result<Self::Value, D::Error> {
+		Deserialize::deserialize(deserializer)
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(
+		self,
+		mut seq: A,
+	) -> Result<Self::Value, A::Error> {
+		let mut arr = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+		while let Some(item) = seq.next_element()? {
+			arr.push(item);
+		}
+		Ok(Node::Array(arr))
+	}
+
+	fn visit_map<A: MapAccess<'de>>(
+		self,
+		mut map: A,
+	) -> Result<Self::Value, A::Error> {
+		let mut obj: Vec<(Cow<'de, str>, Node<'de>)> = Vec::new();
+		// key index, only build for large object
+		let mut index: HashMap<Cow<'de, str>, usize> = HashMap::new();
+
+		while let Some(Key(key)) = map.next_key()? {
+			let val: Node = map.next_value()?;
+
+			let pos = if obj.len() < SCAN_KEYS {
+				obj.iter().position(|(k, _)| *k == key)
+			} else {
+				if index.is_empty() {
+					index = obj
+						.iter()
+						.enumerate()
+						.map(|(i, (k, _))| (k.clone(), i))
+						.collect();
+				}
+				index.get(&key).copied()
+			};
+
+			match pos {
+				Some(pos) => obj[pos].1 = val,
+				None => {
+					if !index.is_empty() {
+						index.insert(key.clone(), obj.len());
+					}
+					obj.push((key, val));
+				}
+			}
+		}
+		Ok(Node::Object(obj))
+	}
+}
+
+// object key which borrow from input if it can
+struct Key<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for Key<'de> {
+	fn deserialize<D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Self, D::Error> {
+		deserializer.deserialize_str(KeyVisitor)
+	}
+}
+
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+	type Value = Key<'de>;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("a string key")
+	}
+
+	fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+		Ok(Key(Cow::Borrowed(v)))
+	}
+
+	fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+		Ok(Key(Cow::Owned(v.to_owned())))
+	}
+
+	fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+		Ok(Key(Cow::Owned(v)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::borrow::Cow;
+
+	use serde_json::json;
+
+	use super::Node;
+
+	#[test]
+	fn test_borrow() {
+		let s = r#"{"plain": "value", "esc\"ape": "a\nb", "n": [1, -2, 1.5, null, true]}"#;
+		let node = Node::parse(s).unwrap();
+		let Node::Object(obj) = &node else { panic!("expect object") };
+		assert!(matches!(obj[0].0, Cow::Borrowed("plain")));
+		assert!(matches!(obj[0].1, Node::String(Cow::Borrowed("value"))));
+		assert!(matches!(obj[1].0, Cow::Owned(_)));
+		assert_eq!(
+			node.to_value(),
+			serde_json::from_str::<serde_json::Value>(s).unwrap()
+		);
+	}
+
+	#[test]
+	fn test_duplicate_key() {
+		let mut s = String::from("{");
+		for i in 0..40 {
+			s.push_str(&format!("\"k{}\": {}, ", i % 20, i));
+		}
+		s.push_str("\"last\": 0}");
+
+		let node = Node::parse(&s).unwrap();
+		assert_eq!(
+			node.to_value(),
+			serde_json::from_str::<serde_json::Value>(&s).unwrap()
+		);
+		assert_eq!(node.to_value()["k3"], json!(23));
+
+		let val = json!({"a": 1, "b": [1, {"c": null}]});
+		assert_eq!(Node::from(&val).to_value(), val);
+	}
+}
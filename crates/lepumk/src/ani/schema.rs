@@ -0,0 +1,390 @@
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::Row;
+
+// typed output schema
+// declare every output column type and how to deal with bad values
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct OutputSchema {
+	// output columns
+	pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Column {
+	// flattened output key
+	pub name: String,
+	// expect value type
+	#[serde(rename = "type")]
+	pub kind: ColumnType,
+	// what to do if value can not covert to kind
+	#[serde(default)]
+	pub on_error: OnError,
+	// value used by on_error default
+	#[serde(default)]
+	pub default: serde_json::Value,
+	// timestamp format like %Y-%m-%d %H:%M:%S, default try rfc3339
+	#[serde(default)]
+	pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+	Int,
+	Float,
+	Bool,
+	String,
+	// epoch millis
+	Timestamp,
+	Json,
+}
+
+#[derive(
+	Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+	// set value as null
+	#[default]
+	Null,
+	// set column default value
+	Default,
+	// drop the whole row
+	DropRow,
+	// fail the whole message
+	Fail,
+}
+
+// coerce value failed and column on_error is fail
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+	pub column: String,
+	pub kind: ColumnType,
+	pub value: serde_json::Value,
+}
+
+impl std::fmt::Display for TypeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"column {} can not covert {} to {:?}",
+			self.column, self.value, self.kind
+		)
+	}
+}
+
+impl std::error::Error for TypeError {}
+
+// rows after coerce and how many value coerce failed
+#[derive(Debug, Default)]
+pub struct Coerced {
+	pub rows: Vec<Row>,
+	pub failures: usize,
+}
+
+impl OutputSchema {
+	// coerce every row by columns
+	// missing column will fill null
+	// output row is in schema order and then the other keys in source order
+	pub fn coerce(&self, rows: Vec<Row>) -> Result<Coerced, TypeError> {
+		let mut res =
+			Coerced { rows: Vec::with_capacity(rows.len()), failures: 0 };
+
+		'row: for row in rows {
+			let mut out = Row::with_capacity(row.len() + self.columns.len());
+			for column in self.columns.iter() {
+				let val =
+					row.get(&column.name).unwrap_or(&serde_json::Value::Null);
+
+				let val = match column.coerce(val) {
+					Some(val) => val,
+					None => {
+						res.failures += 1;
+						match column.on_error {
+							OnError::Null => serde_json::Value::Null,
+							OnError::Default => column.default.clone(),
+							OnError::DropRow => continue 'row,
+							OnError::Fail => {
+								return Err(TypeError {
+									column: column.name.clone(),
+									kind: column.kind,
+									value: val.clone(),
+								});
+							}
+						}
+					}
+				};
+				out.insert(column.name.clone(), val);
+			}
+
+			for (key, val) in row {
+				if !out.contains_key(&key) {
+					out.insert(key, val);
+				}
+			}
+			res.rows.push(out);
+		}
+
+		Ok(res)
+	}
+}
+
+impl Column {
+	// covert value into column type, null is always valid
+	pub fn coerce(&self, val: &serde_json::Value) -> Option<serde_json::Value> {
+		use serde_json::Value;
+
+		if val.is_null() {
+			return Some(Value::Null);
+		}
+
+		match self.kind {
+			ColumnType::Int => to_int(val).map(Value::from),
+			ColumnType::Float => to_float(val).and_then(|f| {
+				serde_json::Number::from_f64(f).map(Value::Number)
+			}),
+			ColumnType::Bool => to_bool(val).map(Value::Bool),
+			ColumnType::String => match val {
+				Value::String(s) => Some(Value::String(s.clone())),
+				other => Some(Value::String(other.to_string())),
+			},
+			ColumnType::Timestamp => {
+				to_timestamp(val, self.format.as_deref()).map(Value::from)
+			}
+			ColumnType::Json => match val {
+				Value::String(s) => serde_json::from_str(s).ok(),
+				other => Some(other.clone()),
+			},
+		}
+	}
+}
+
+fn to_int(val: &serde_json::Value) -> Option<i64> {
+	match val {
+		serde_json::Value::Number(n) => match n.as_i64() {
+			Some(i) => Some(i),
+			None => n.as_f64().and_then(float_to_int),
+		},
+		serde_json::Value::String(s) => {
+			let s = s.trim();
+			match s.parse::<i64>() {
+				Ok(i) => Some(i),
+				Err(_) => s.parse::<f64>().ok().and_then(float_to_int),
+			}
+		}
+		serde_json::Value::Bool(b) => Some(*b as i64),
+		_ => None,
+	}
+}
+
+// only float without fraction can covert to int
+fn float_to_int(f: f64) -> Option<i64> {
+	if f.fract() == 0.0 && f >= i64::MIN as f64 && f <= i64::MAX as f64 {
+		Some(f as i64)
+	} else {
+		None
+	}
+}
+
+fn to_float(val: &serde_json::Value) -> Option<f64> {
+	let f = match val {
+		serde_json::Value::Number(n) => n.as_f64(),
+		serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+		serde_json::Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+		_ => None,
+	};
+	f.filter(|f| f.is_finite())
+}
+
+fn to_bool(val: &serde_json::Value) -> Option<bool> {
+	match val {
+		serde_json::Value::Bool(b) => Some(*b),
+		serde_json::Value::Number(n) => match n.as_i64() {
+			Some(0) => Some(false),
+			Some(1) => Some(true),
+			_ => None,
+		},
+		serde_json::Value::String(s) => {
+			match s.trim().to_ascii_lowercase().as_str() {
+				"true" | "1" | "yes" | "y" => Some(true),
+				"false" | "0" | "no" | "n" => Some(false),
+				_ => None,
+			}
+		}
+		_ => None,
+	}
+}
+
+// epoch seconds smaller than this, larger is epoch millis
+const MAX_EPOCH_SECONDS: i64 = 100_000_000_000;
+
+// covert value into epoch millis
+// number less than MAX_EPOCH_SECONDS treat as seconds
+pub fn to_timestamp(
+	val: &serde_json::Value,
+	format: Option<&str>,
+) -> Option<i64> {
+	match val {
+		serde_json::Value::Number(_) => to_int(val).map(epoch_millis),
+		serde_json::Value::String(s) => {
+			let s = s.trim();
+			if let Ok(i) = s.parse::<i64>() {
+				return Some(epoch_millis(i));
+			}
+			parse_datetime(s, format)
+		}
+		_ => None,
+	}
+}
+
+fn epoch_millis(i: i64) -> i64 {
+	if i.unsigned_abs() < MAX_EPOCH_SECONDS as u64 {
+		i * 1000
+	} else {
+		i
+	}
+}
+
+// parse datetime string into epoch millis
+// datetime without timezone treat as utc
+fn parse_datetime(s: &str, format: Option<&str>) -> Option<i64> {
+	const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+	if let Some(format) = format {
+		if let Ok(dt) = DateTime::parse_from_str(s, format) {
+			return Some(dt.timestamp_millis());
+		}
+		return NaiveDateTime::parse_from_str(s, format)
+			.ok()
+			.map(|dt| dt.and_utc().timestamp_millis());
+	}
+
+	if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+		return Some(dt.timestamp_millis());
+	}
+
+	NaiveDateTime::parse_from_str(s, DEFAULT_FORMAT)
+		.ok()
+		.map(|dt| dt.and_utc().timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::OnError;
+	use super::OutputSchema;
+	use super::Row;
+
+	fn schema(on_error: OnError) -> OutputSchema {
+		let mut schema: OutputSchema = serde_json::from_value(json!({
+			"columns": [
+				{"name": "id", "type": "int"},
+				{"name": "price", "type": "float"},
+				{"name": "ok", "type": "bool"},
+				{"name": "name", "type": "string"},
+				{"name": "ts", "type": "timestamp"},
+				{"name": "ext", "type": "json", "default": {}}
+			]
+		}))
+		.unwrap();
+		for column in schema.columns.iter_mut() {
+			column.on_error = on_error;
+		}
+		schema
+	}
+
+	fn row(val: serde_json::Value) -> Row {
+		serde_json::from_value(val).unwrap()
+	}
+
+	#[test]
+	fn test_coerce() {
+		let rows = vec![row(json!({
+			"id": "12",
+			"price": "1.5",
+			"ok": "yes",
+			"name": 7,
+			"ts": "2024-01-02T03:04:05Z",
+			"ext": "{\"a\":1}",
+			"other": "keep"
+		}))];
+
+		let res = schema(OnError::Fail).coerce(rows).unwrap();
+		assert_eq!(res.failures, 0);
+		assert_eq!(res.rows[0]["id"], json!(12));
+		assert_eq!(res.rows[0]["price"], json!(1.5));
+		assert_eq!(res.rows[0]["ok"], json!(true));
+		assert_eq!(res.rows[0]["name"], json!("7"));
+		assert_eq!(res.rows[0]["ts"], json!(1704164645000_i64));
+		assert_eq!(res.rows[0]["ext"], json!({"a": 1}));
+		assert_eq!(res.rows[0]["other"], json!("keep"));
+	}
+
+	#[test]
+	fn test_coerce_missing_and_null() {
+		let res = schema(OnError::Fail)
+			.coerce(vec![row(json!({"id": null}))])
+			.unwrap();
+		assert_eq!(res.failures, 0);
+		assert_eq!(res.rows[0].len(), 6);
+		assert!(res.rows[0]["id"].is_null());
+		assert!(res.rows[0]["ts"].is_null());
+	}
+
+	#[test]
+	fn test_schema_order() {
+		let rows = vec![row(json!({"z": 1, "name": "n", "a": 2, "id": 3}))];
+		let res = schema(OnError::Fail).coerce(rows).unwrap();
+		let keys = res.rows[0].keys().map(|k| k.as_str()).collect::<Vec<_>>();
+		assert_eq!(
+			keys,
+			vec!["id", "price", "ok", "name", "ts", "ext", "z", "a"]
+		);
+	}
+
+	#[test]
+	fn test_timestamp() {
+		let res = schema(OnError::Fail)
+			.coerce(vec![
+				row(json!({"ts": 1704164645})),
+				row(json!({"ts": 1704164645123_i64})),
+				row(json!({"ts": "2024-01-02 03:04:05"})),
+				row(json!({"ts": i64::MIN})),
+				row(json!({"ts": i64::MIN.to_string()})),
+			])
+			.unwrap();
+		assert_eq!(res.rows[0]["ts"], json!(1704164645000_i64));
+		assert_eq!(res.rows[1]["ts"], json!(1704164645123_i64));
+		assert_eq!(res.rows[2]["ts"], json!(1704164645000_i64));
+		// abs of i64::MIN overflow
+		assert_eq!(res.rows[3]["ts"], json!(i64::MIN));
+		assert_eq!(res.rows[4]["ts"], json!(i64::MIN));
+	}
+
+	#[test]
+	fn test_on_error() {
+		let bad = || {
+			vec![row(json!({"id": "abc", "ext": "{"})), row(json!({"id": 1.5}))]
+		};
+
+		let res = schema(OnError::Null).coerce(bad()).unwrap();
+		assert_eq!(res.failures, 3);
+		assert!(res.rows[0]["id"].is_null());
+
+		let res = schema(OnError::Default).coerce(bad()).unwrap();
+		assert_eq!(res.rows[0]["ext"], json!({}));
+
+		let res = schema(OnError::DropRow).coerce(bad()).unwrap();
+		assert_eq!(res.failures, 2);
+		assert!(res.rows.is_empty());
+
+		let err = schema(OnError::Fail).coerce(bad()).unwrap_err();
+		assert_eq!(err.column, "id");
+	}
+}
@@ -0,0 +1,67 @@
+use indexmap::IndexSet;
+
+use serde::Serialize;
+
+// what parser do with the value at one flattened key
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Hash)]
+pub struct Trace {
+	// flattened key of the value
+	pub key: String,
+	pub decision: Decision,
+	// option rule make the decision, none is the normal flatten
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rule: Option<Rule>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+	// output the value as it is
+	Keep,
+	// array or object expand into sub keys
+	Expand,
+	// output the value as json without expand
+	Fold,
+	// output the default value
+	Default,
+	// value not in output
+	Drop,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+	// key not in keys with strict mode
+	Strict,
+	Ignore,
+	Fold,
+	// value is null, empty array or empty object
+	Default,
+	// value deeper than max_depth
+	Depth,
+}
+
+// collect trace in walk order, same key and decision only keep the first
+#[derive(Debug, Default)]
+pub(crate) struct Tracer(Option<IndexSet<Trace>>);
+
+impl Tracer {
+	pub(crate) fn enable() -> Self {
+		Self(Some(IndexSet::new()))
+	}
+
+	pub(crate) fn record(
+		&mut self,
+		key: &str,
+		decision: Decision,
+		rule: Option<Rule>,
+	) {
+		if let Some(set) = self.0.as_mut() {
+			set.insert(Trace { key: key.to_owned(), decision, rule });
+		}
+	}
+
+	pub(crate) fn finish(self) -> Vec<Trace> {
+		self.0.map(|set| set.into_iter().collect()).unwrap_or_default()
+	}
+}
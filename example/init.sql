@@ -17,6 +17,14 @@ CREATE TABLE `task_info` (
     `heartbeat` bigint NOT NULL DEFAULT '0' COMMENT 'last heartbeat',
     `updated_at` bigint NOT NULL DEFAULT '0' COMMENT 'update time',
     `created_at` bigint NOT NULL DEFAULT '0' COMMENT 'created time',
+    `property_item` json DEFAULT NULL COMMENT 'property item',
+    `handle_num` bigint NOT NULL DEFAULT '0' COMMENT 'total handle message',
+    `handle_err` bigint NOT NULL DEFAULT '0' COMMENT 'handle message error',
+    `handle_type_err` bigint NOT NULL DEFAULT '0' COMMENT 'message with type coerce error',
+    `dedup_hit` bigint NOT NULL DEFAULT '0' COMMENT 'duplicate row dropped',
+    `dedup_miss` bigint NOT NULL DEFAULT '0' COMMENT 'row pass dedup',
+    `throttled` bigint NOT NULL DEFAULT '0' COMMENT 'message wait for rate limit',
+    `sampled_out` bigint NOT NULL DEFAULT '0' COMMENT 'row dropped by sample',
     PRIMARY KEY (`id`)
 ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci;
 
@@ -31,3 +39,11 @@ CREATE TABLE `task_log` (
     PRIMARY KEY (`id`),
     KEY `idx_task_id` (`task_id`) COMMENT 'task index '
 ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci;
+
+-- hydrogen.task_cursor definition
+CREATE TABLE `task_cursor` (
+    `task_id` bigint NOT NULL COMMENT 'task id',
+    `position` text NOT NULL COMMENT 'source position',
+    `updated_at` bigint NOT NULL DEFAULT '0' COMMENT 'update time',
+    PRIMARY KEY (`task_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci;
@@ -0,0 +1,524 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use lepumk::ani::schema::to_timestamp;
+use lepumk::ani::Row;
+
+use crate::core::Ack;
+use crate::core::CoreMsg;
+
+// group rows into event time windows and emit aggregates when window close
+// task with aggregate only sink aggregate rows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateConfig {
+	// group key fields
+	#[serde(default)]
+	pub group_by: Vec<String>,
+	// event time field, epoch or datetime string
+	pub time_field: String,
+	// window size seconds
+	pub size: u64,
+	// sliding step seconds, none is tumbling window
+	#[serde(default)]
+	pub slide: Option<u64>,
+	// seconds a window wait for late row after max event time pass the end
+	#[serde(default)]
+	pub lateness: u64,
+	pub metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metric {
+	// output column
+	pub name: String,
+	pub op: AggOp,
+	// input field, count without field count rows
+	#[serde(default)]
+	pub field: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggOp {
+	Count,
+	Sum,
+	Min,
+	Max,
+	// approx distinct count by hyperloglog
+	Distinct,
+}
+
+enum Acc {
+	Count(i64),
+	Sum { int: i64, float: f64, is_float: bool, has: bool },
+	Min(Option<(f64, serde_json::Value)>),
+	Max(Option<(f64, serde_json::Value)>),
+	Distinct(Box<HyperLogLog>),
+}
+
+impl Acc {
+	fn new(op: AggOp) -> Self {
+		match op {
+			AggOp::Count => Acc::Count(0),
+			AggOp::Sum => Acc::Sum { int: 0, float: 0.0, is_float: false, has: false },
+			AggOp::Min => Acc::Min(None),
+			AggOp::Max => Acc::Max(None),
+			AggOp::Distinct => Acc::Distinct(Box::default()),
+		}
+	}
+
+	// null or missing value is skipped, count without field count rows
+	fn add(&mut self, val: Option<&serde_json::Value>) {
+		let val = match val {
+			Some(serde_json::Value::Null) => return,
+			Some(val) => val,
+			None => {
+				if let Acc::Count(n) = self {
+					*n += 1;
+				}
+				return;
+			}
+		};
+
+		match self {
+			Acc::Count(n) => *n += 1,
+			Acc::Sum { int, float, is_float, has } => {
+				if let Some(i) = val.as_i64() {
+					// overflow fall back to the float sum
+					match int.checked_add(i) {
+						Some(sum) => *int = sum,
+						None => *is_float = true,
+					}
+					*float += i as f64;
+				} else if let Some(f) = number(val) {
+					*is_float = true;
+					*float += f;
+				} else {
+					return;
+				}
+				*has = true;
+			}
+			Acc::Min(cur) => {
+				if let Some(f) = number(val) {
+					if cur.as_ref().map_or(true, |(c, _)| f < *c) {
+						*cur = Some((f, val.clone()));
+					}
+				}
+			}
+			Acc::Max(cur) => {
+				if let Some(f) = number(val) {
+					if cur.as_ref().map_or(true, |(c, _)| f > *c) {
+						*cur = Some((f, val.clone()));
+					}
+				}
+			}
+			Acc::Distinct(hll) => hll.add(&val.to_string()),
+		}
+	}
+
+	fn value(&self) -> serde_json::Value {
+		match self {
+			Acc::Count(n) => (*n).into(),
+			Acc::Sum { has: false, .. } => serde_json::Value::Null,
+			Acc::Sum { int, is_float: false, .. } => (*int).into(),
+			Acc::Sum { float, .. } => {
+				serde_json::Number::from_f64(*float).map_or(serde_json::Value::Null, Into::into)
+			}
+			Acc::Min(cur) | Acc::Max(cur) => {
+				cur.as_ref().map_or(serde_json::Value::Null, |(_, v)| v.clone())
+			}
+			Acc::Distinct(hll) => hll.count().into(),
+		}
+	}
+}
+
+// number or number string
+fn number(val: &serde_json::Value) -> Option<f64> {
+	match val {
+		serde_json::Value::Number(n) => n.as_f64(),
+		serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+		_ => None,
+	}
+	.filter(|f| f.is_finite())
+}
+
+// register index bits, 4096 registers about 1.6% error
+const HLL_BITS: u32 = 12;
+const HLL_SIZE: usize = 1 << HLL_BITS;
+
+pub struct HyperLogLog {
+	registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+	fn default() -> Self {
+		Self { registers: vec![0; HLL_SIZE] }
+	}
+}
+
+impl HyperLogLog {
+	fn add(&mut self, s: &str) {
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		s.hash(&mut hasher);
+		let hash = hasher.finish();
+		let index = (hash >> (64 - HLL_BITS)) as usize;
+		let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+		if rank > self.registers[index] {
+			self.registers[index] = rank;
+		}
+	}
+
+	fn count(&self) -> i64 {
+		let m = HLL_SIZE as f64;
+		let alpha = 0.7213 / (1.0 + 1.079 / m);
+		let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+		let estimate = alpha * m * m / sum;
+
+		let zeros = self.registers.iter().filter(|r| **r == 0).count();
+		// small range use linear counting
+		if estimate <= 2.5 * m && zeros > 0 {
+			return (m * (m / zeros as f64).ln()).round() as i64;
+		}
+		estimate.round() as i64
+	}
+}
+
+// group key to group values and accumulators, with confirms and latest ingest time of input messages
+#[derive(Default)]
+struct Window {
+	groups: HashMap<String, (Vec<serde_json::Value>, Vec<Acc>)>,
+	acks: Vec<Ack>,
+	timestamp: Option<i64>,
+}
+
+// window start to window
+type Windows = BTreeMap<i64, Window>;
+
+pub struct Aggregator {
+	conf: AggregateConfig,
+	windows: Windows,
+	// max event time seen
+	max_time: i64,
+	// row dropped for missing time or too late
+	pub dropped: i64,
+	// windows the rows of current message added to
+	touched: BTreeSet<i64>,
+	// confirms of closed windows, settled when the aggregate rows are sunk
+	released: Vec<Ack>,
+	released_timestamp: Option<i64>,
+}
+
+impl Aggregator {
+	pub fn new(conf: &AggregateConfig) -> anyhow::Result<Self> {
+		if conf.size == 0 {
+			anyhow::bail!("aggregate window size must larger than 0");
+		}
+		if conf.slide == Some(0) || conf.slide.is_some_and(|slide| slide > conf.size) {
+			anyhow::bail!("aggregate slide must in (0, size]");
+		}
+		if conf.metrics.is_empty() {
+			anyhow::bail!("aggregate need at least one metric");
+		}
+		for metric in conf.metrics.iter() {
+			if metric.op != AggOp::Count && metric.field.is_none() {
+				anyhow::bail!("aggregate metric {} need field", metric.name);
+			}
+		}
+		Ok(Self {
+			conf: conf.clone(),
+			windows: BTreeMap::new(),
+			max_time: i64::MIN,
+			dropped: 0,
+			touched: BTreeSet::new(),
+			released: vec![],
+			released_timestamp: None,
+		})
+	}
+
+	fn size(&self) -> i64 {
+		self.conf.size as i64 * 1000
+	}
+
+	// window start contain the time
+	fn starts(&self, time: i64) -> Vec<i64> {
+		let size = self.size();
+		let slide = self.conf.slide.map_or(size, |slide| slide as i64 * 1000);
+		let mut start = time.div_euclid(slide) * slide;
+		let mut res = vec![];
+		while start > time - size {
+			res.push(start);
+			start -= slide;
+		}
+		res
+	}
+
+	// watermark, window end not after it is closed
+	fn watermark(&self) -> i64 {
+		self.max_time.saturating_sub(self.conf.lateness as i64 * 1000)
+	}
+
+	pub fn add(&mut self, row: &Row) {
+		let Some(time) = row.get(&self.conf.time_field).and_then(|val| to_timestamp(val, None))
+		else {
+			self.dropped += 1;
+			return;
+		};
+
+		let watermark = self.watermark();
+		let starts = self
+			.starts(time)
+			.into_iter()
+			.filter(|start| start + self.size() > watermark)
+			.collect::<Vec<_>>();
+		if starts.is_empty() {
+			self.dropped += 1;
+			return;
+		}
+		self.max_time = self.max_time.max(time);
+
+		let vals = self
+			.conf
+			.group_by
+			.iter()
+			.map(|key| row.get(key).cloned().unwrap_or(serde_json::Value::Null))
+			.collect::<Vec<_>>();
+		let key = serde_json::json!(vals).to_string();
+
+		for start in starts {
+			self.touched.insert(start);
+			let group =
+				self.windows.entry(start).or_default().groups.entry(key.clone()).or_insert_with(
+					|| (vals.clone(), self.conf.metrics.iter().map(|m| Acc::new(m.op)).collect()),
+				);
+			for (acc, metric) in group.1.iter_mut().zip(self.conf.metrics.iter()) {
+				acc.add(metric.field.as_ref().and_then(|field| row.get(field)));
+			}
+		}
+	}
+
+	// rows of a message, the windows they are in hold its confirm until closed
+	pub fn add_msg(&mut self, msg: CoreMsg) {
+		for row in msg.result.iter() {
+			self.add(row);
+		}
+		for start in std::mem::take(&mut self.touched) {
+			if let Some(window) = self.windows.get_mut(&start) {
+				window.acks.extend(msg.acks.iter().cloned());
+				window.timestamp = window.timestamp.max(msg.timestamp);
+			}
+		}
+		// handed over to windows, or rows are all late
+		msg.done();
+	}
+
+	// source is idle, event time move with wall clock so open windows can close
+	pub fn idle(&mut self, elapsed: i64) {
+		if self.max_time != i64::MIN {
+			self.max_time = self.max_time.saturating_add(elapsed);
+		}
+	}
+
+	// aggregate message of closed windows with confirms of their inputs
+	pub fn flush(&mut self, force: bool) -> Option<CoreMsg> {
+		let rows = self.close(force);
+		let acks = std::mem::take(&mut self.released);
+		let timestamp = self.released_timestamp.take();
+		if rows.is_empty() {
+			return None;
+		}
+		Some(CoreMsg::default().with_result(rows).with_timestamp(timestamp).with_acks(acks))
+	}
+
+	// rows of closed windows, all windows with force
+	pub fn close(&mut self, force: bool) -> Vec<Row> {
+		let size = self.size();
+		let watermark = self.watermark();
+		let mut res = vec![];
+		while let Some(entry) = self.windows.first_entry() {
+			let start = *entry.key();
+			if !force && start + size > watermark {
+				break;
+			}
+			let window = entry.remove();
+			self.released.extend(window.acks);
+			self.released_timestamp = self.released_timestamp.max(window.timestamp);
+			for (_, (vals, accs)) in window.groups {
+				let mut row = Row::with_capacity(vals.len() + accs.len() + 2);
+				for (key, val) in self.conf.group_by.iter().zip(vals) {
+					row.insert(key.clone(), val);
+				}
+				row.insert("window_start".to_owned(), start.into());
+				row.insert("window_end".to_owned(), (start + size).into());
+				for (metric, acc) in self.conf.metrics.iter().zip(accs.iter()) {
+					row.insert(metric.name.clone(), acc.value());
+				}
+				res.push(row);
+			}
+		}
+		res
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use tokio::sync::mpsc;
+
+	use crate::core::Ack;
+	use crate::core::AckKind;
+	use crate::core::CoreMsg;
+
+	use super::AggregateConfig;
+	use super::Aggregator;
+	use super::HyperLogLog;
+
+	fn aggregator(conf: serde_json::Value) -> Aggregator {
+		let conf: AggregateConfig = serde_json::from_value(conf).unwrap();
+		Aggregator::new(&conf).unwrap()
+	}
+
+	fn add(agg: &mut Aggregator, rows: serde_json::Value) {
+		let rows: Vec<Row> = serde_json::from_value(rows).unwrap();
+		for row in rows.iter() {
+			agg.add(row);
+		}
+	}
+
+	#[test]
+	fn test_tumbling() {
+		let mut agg = aggregator(json!({
+			"group_by": ["k"],
+			"time_field": "ts",
+			"size": 60,
+			"lateness": 10,
+			"metrics": [
+				{"name": "cnt", "op": "count"},
+				{"name": "sum", "op": "sum", "field": "v"},
+				{"name": "min", "op": "min", "field": "v"},
+				{"name": "max", "op": "max", "field": "v"},
+				{"name": "uv", "op": "distinct", "field": "u"}
+			]
+		}));
+
+		add(
+			&mut agg,
+			json!([
+				{"k": "a", "ts": 0, "v": 1, "u": "x"},
+				{"k": "a", "ts": 30, "v": 2.5, "u": "y"},
+				{"k": "b", "ts": 59, "v": "3", "u": "x"},
+				{"k": "a", "ts": 65, "v": 1, "u": "x"}
+			]),
+		);
+		// max time 65 minus lateness 10 not pass the first window end
+		assert!(agg.close(false).is_empty());
+
+		// late row in lateness still count
+		add(&mut agg, json!([{"k": "a", "ts": 40, "v": 1, "u": "x"}, {"k": "a", "ts": 75}]));
+		let mut rows = agg.close(false);
+		rows.sort_by_key(|row| row["k"].to_string());
+		assert_eq!(
+			json!(rows),
+			json!([
+				{"k": "a", "window_start": 0, "window_end": 60000, "cnt": 3, "sum": 4.5, "min": 1, "max": 2.5, "uv": 2},
+				{"k": "b", "window_start": 0, "window_end": 60000, "cnt": 1, "sum": 3.0, "min": "3", "max": "3", "uv": 1}
+			])
+		);
+
+		// too late and without time are dropped
+		add(&mut agg, json!([{"k": "a", "ts": 10}, {"k": "a"}]));
+		assert_eq!(agg.dropped, 2);
+
+		let rows = agg.close(true);
+		assert_eq!(rows.len(), 1);
+		assert_eq!(rows[0]["cnt"], json!(2));
+		assert_eq!(rows[0]["sum"], json!(1));
+	}
+
+	#[test]
+	fn test_sum_overflow() {
+		let mut agg = aggregator(json!({
+			"time_field": "ts",
+			"size": 60,
+			"metrics": [{"name": "sum", "op": "sum", "field": "v"}]
+		}));
+		add(&mut agg, json!([{"ts": 0, "v": i64::MAX}, {"ts": 1, "v": 1}]));
+		let rows = agg.close(true);
+		assert_eq!(rows[0]["sum"], json!(i64::MAX as f64 + 1.0));
+	}
+
+	#[test]
+	fn test_sliding() {
+		let mut agg = aggregator(json!({
+			"time_field": "ts",
+			"size": 60,
+			"slide": 30,
+			"metrics": [{"name": "cnt", "op": "count"}]
+		}));
+		add(&mut agg, json!([{"ts": 45}, {"ts": 100}]));
+		let rows = agg.close(false);
+		assert_eq!(
+			json!(rows),
+			json!([
+				{"window_start": 0, "window_end": 60000, "cnt": 1},
+				{"window_start": 30000, "window_end": 90000, "cnt": 1}
+			])
+		);
+		assert_eq!(agg.close(true).len(), 2);
+	}
+
+	#[test]
+	fn test_aggregate_ack() {
+		let mut agg = aggregator(json!({
+			"time_field": "ts",
+			"size": 60,
+			"metrics": [{"name": "cnt", "op": "count"}]
+		}));
+		let (acks, mut r) = mpsc::unbounded_channel();
+		let msg = |id: &str, rows: serde_json::Value, timestamp: i64| {
+			CoreMsg::default()
+				.with_result(serde_json::from_value(rows).unwrap())
+				.with_timestamp(Some(timestamp))
+				.with_ack(Some(Ack::new(id.to_owned(), acks.clone())))
+		};
+		agg.add_msg(msg("1", json!([{"ts": 10}, {"ts": 20}]), 100));
+		agg.add_msg(msg("2", json!([{"ts": 30}]), 200));
+		// rows are all late
+		agg.add_msg(msg("3", json!([{"v": 1}]), 300));
+		assert_eq!(r.try_recv().unwrap().id, "3");
+		assert!(agg.flush(false).is_none());
+		assert!(r.try_recv().is_err());
+
+		// idle source close the window
+		agg.idle(40_000);
+		let out = agg.flush(false).unwrap();
+		assert_eq!(json!(out.result), json!([{"window_start": 0, "window_end": 60000, "cnt": 3}]));
+		assert_eq!(out.timestamp, Some(200));
+		assert!(r.try_recv().is_err());
+
+		out.done();
+		let mut settled = [r.try_recv().unwrap(), r.try_recv().unwrap()];
+		settled.sort_by(|a, b| a.id.cmp(&b.id));
+		let settled = settled.iter().map(|a| (a.id.as_str(), a.kind)).collect::<Vec<_>>();
+		assert_eq!(settled, [("1", AckKind::Done), ("2", AckKind::Done)]);
+	}
+
+	#[test]
+	fn test_hyperloglog() {
+		let mut hll = HyperLogLog::default();
+		for i in 0..10_000 {
+			hll.add(&i.to_string());
+			hll.add(&i.to_string());
+		}
+		let count = hll.count();
+		assert!((9_500..10_500).contains(&count), "count {}", count);
+	}
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+use lru::LruCache;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use tokio::sync::mpsc;
+
+use tracing::info;
+
+use lepumk::ani::Row;
+
+use crate::conf::DedupConf;
+use crate::core::Ack;
+use crate::core::AckKind;
+use crate::core::Acked;
+use crate::core::CoreMsg;
+use crate::util::row_digest;
+
+// dir of snapshots, load from app config only
+static SNAPSHOT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn init_snapshot_dir(conf: &DedupConf) {
+	let _ = SNAPSHOT_DIR.set(PathBuf::from(&conf.snapshot_dir));
+}
+
+fn snapshot_dir() -> PathBuf {
+	SNAPSHOT_DIR.get().cloned().unwrap_or_else(|| PathBuf::from(DedupConf::default().snapshot_dir))
+}
+
+// drop row seen before, seen rows is bounded by capacity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DedupConfig {
+	// key fields, empty use the whole row
+	#[serde(default)]
+	pub keys: Vec<String>,
+	// seconds a seen row is duplicate, 0 is until evicted
+	#[serde(default)]
+	pub window: u64,
+	// max seen rows keep in memory, least recently used is evicted
+	#[serde(default = "default_capacity")]
+	pub capacity: usize,
+	// keep seen rows in a snapshot of the task under app snapshot dir, load when task start
+	#[serde(default)]
+	pub snapshot: bool,
+}
+
+fn default_capacity() -> usize {
+	100_000
+}
+
+// rows passed dedup, seen once the sinker writes them
+pub struct Filtered {
+	pub hit: i64,
+	pub miss: i64,
+	pub keys: Vec<u128>,
+}
+
+pub struct Dedup {
+	conf: DedupConfig,
+	// row hash to epoch millis first seen
+	seen: Mutex<LruCache<u128, i64>>,
+	// row hash of passed rows not settled by the sinker yet
+	pending: Mutex<HashMap<u128, i64>>,
+	path: Option<PathBuf>,
+	// fields not in whole row hash like injected ingest time
+	skip: Vec<String>,
+}
+
+impl Dedup {
+	pub fn new(conf: &DedupConfig, task_id: i64) -> anyhow::Result<Self> {
+		let capacity = NonZeroUsize::new(conf.capacity)
+			.with_context(|| "dedup capacity must larger than 0".to_string())?;
+		let path = conf.snapshot.then(|| snapshot_dir().join(format!("{}.json", task_id)));
+		let seen = Mutex::new(LruCache::new(capacity));
+		let pending = Mutex::new(HashMap::new());
+		let dedup = Self { conf: conf.clone(), seen, pending, path, skip: vec![] };
+		dedup.load()?;
+		Ok(dedup)
+	}
+
+	pub fn with_skip(self, skip: Vec<String>) -> Self {
+		Self { skip, ..self }
+	}
+
+	// stable between restart, snapshot keep the hash
+	fn hash(&self, row: &Row) -> u128 {
+		let digest = row_digest(row, &self.conf.keys, &self.skip);
+		let mut buf = [0u8; 16];
+		buf.copy_from_slice(&digest[..16]);
+		u128::from_be_bytes(buf)
+	}
+
+	fn expired(&self, seen: i64, now: i64) -> bool {
+		self.conf.window > 0 && now - seen > self.conf.window as i64 * 1000
+	}
+
+	// drop rows seen or pending, keys of passed rows are pending until settle
+	pub fn filter(&self, rows: &mut Vec<Row>, now: i64) -> Filtered {
+		let mut filtered = Filtered { hit: 0, miss: 0, keys: vec![] };
+		let mut seen = self.seen.lock().unwrap();
+		let mut pending = self.pending.lock().unwrap();
+		rows.retain(|row| {
+			let key = self.hash(row);
+			let at = pending.get(&key).or_else(|| seen.get(&key)).copied();
+			match at {
+				Some(at) if !self.expired(at, now) => {
+					filtered.hit += 1;
+					false
+				}
+				_ => {
+					pending.insert(key, now);
+					filtered.keys.push(key);
+					filtered.miss += 1;
+					true
+				}
+			}
+		});
+		filtered
+	}
+
+	// rows of keys are sunk and seen, or failed and pass again when redelivered
+	pub fn settle(&self, keys: Vec<u128>, sunk: bool) {
+		let mut seen = self.seen.lock().unwrap();
+		let mut pending = self.pending.lock().unwrap();
+		for key in keys {
+			let Some(at) = pending.remove(&key) else {
+				continue;
+			};
+			if sunk {
+				seen.put(key, at);
+			}
+		}
+	}
+}
+
+// passed keys and source acks of a message in flight, settled with the sinker ack
+type Inflight = HashMap<String, (Vec<u128>, Vec<Ack>)>;
+
+impl Dedup {
+	// drop duplicate rows of messages, count hit and miss of every message
+	pub async fn run(
+		&self,
+		mut receiver: mpsc::Receiver<CoreMsg>,
+		sender: mpsc::Sender<CoreMsg>,
+		count: impl Fn(i64, i64),
+	) -> anyhow::Result<()> {
+		let (acks, mut settled) = mpsc::unbounded_channel();
+		let mut inflight = Inflight::new();
+		let mut seq: u64 = 0;
+		loop {
+			tokio::select! {
+				msg = receiver.recv() => {
+					let Some(mut msg) = msg else {
+						break;
+					};
+					let now = chrono::Utc::now().timestamp_millis();
+					let filtered = self.filter(&mut msg.result, now);
+					count(filtered.hit, filtered.miss);
+					// rows are duplicates of sunk or in flight rows
+					if msg.result.is_empty() {
+						msg.done();
+						continue;
+					}
+					let ack = Ack::new(seq.to_string(), acks.clone());
+					inflight.insert(seq.to_string(), (filtered.keys, std::mem::take(&mut msg.acks)));
+					seq += 1;
+					sender.send(msg.with_acks(vec![ack])).await?;
+				},
+				Some(acked) = settled.recv() => self.settle_msg(&mut inflight, acked),
+			}
+		}
+
+		info!("close dedup sender");
+		drop(sender);
+		drop(acks);
+		// wait the sinker to settle rows in flight
+		while let Some(acked) = settled.recv().await {
+			self.settle_msg(&mut inflight, acked);
+		}
+		Ok(())
+	}
+
+	// keys are seen once sunk, source acks follow the sinker
+	fn settle_msg(&self, inflight: &mut Inflight, acked: Acked) {
+		let Some((keys, source)) = inflight.remove(&acked.id) else {
+			return;
+		};
+		self.settle(keys, acked.kind == AckKind::Done);
+		match acked.kind {
+			AckKind::Done => source.into_iter().for_each(Ack::done),
+			AckKind::Reject => source.into_iter().for_each(Ack::reject),
+			// dropped unsettled, the source deliver it again
+			AckKind::Retry => drop(source),
+		}
+	}
+}
+
+impl Dedup {
+	// load snapshot, skip the expired
+	fn load(&self) -> anyhow::Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		let content = match std::fs::read_to_string(path) {
+			Ok(content) => content,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+			Err(err) => anyhow::bail!("read dedup snapshot {:?} error {:?}", path, err),
+		};
+		let items: Vec<(String, i64)> = serde_json::from_str(&content)
+			.with_context(|| format!("decode dedup snapshot {:?}", path))?;
+
+		let now = chrono::Utc::now().timestamp_millis();
+		let mut seen = self.seen.lock().unwrap();
+		for (key, at) in items {
+			if self.expired(at, now) {
+				continue;
+			}
+			if let Ok(key) = u128::from_str_radix(&key, 16) {
+				seen.put(key, at);
+			}
+		}
+		info!("load dedup snapshot {:?} size {}", path, seen.len());
+		Ok(())
+	}
+
+	// write seen rows from least recently used, then rename into path
+	pub async fn snapshot(&self) -> anyhow::Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		let items = {
+			let seen = self.seen.lock().unwrap();
+			seen.iter().rev().map(|(key, at)| (format!("{:x}", key), *at)).collect::<Vec<_>>()
+		};
+
+		if let Some(dir) = path.parent() {
+			tokio::fs::create_dir_all(dir)
+				.await
+				.with_context(|| format!("create dedup snapshot dir {:?}", dir))?;
+		}
+		let tmp = path.with_extension("json.tmp");
+		tokio::fs::write(&tmp, serde_json::to_vec(&items)?)
+			.await
+			.with_context(|| format!("write dedup snapshot {:?}", tmp))?;
+		tokio::fs::rename(&tmp, path)
+			.await
+			.with_context(|| format!("rename dedup snapshot {:?}", path))?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use tokio::sync::mpsc;
+
+	use super::init_snapshot_dir;
+	use super::Dedup;
+	use super::DedupConfig;
+	use crate::conf::DedupConf;
+	use crate::core::Ack;
+	use crate::core::AckKind;
+	use crate::core::Acked;
+	use crate::core::CoreMsg;
+
+	fn rows(val: serde_json::Value) -> Vec<Row> {
+		serde_json::from_value(val).unwrap()
+	}
+
+	fn dedup(conf: serde_json::Value) -> Dedup {
+		let conf: DedupConfig = serde_json::from_value(conf).unwrap();
+		Dedup::new(&conf, 7).unwrap()
+	}
+
+	// filter and sink the passed rows
+	fn sunk(d: &Dedup, rows: &mut Vec<Row>, now: i64) -> (i64, i64) {
+		let filtered = d.filter(rows, now);
+		d.settle(filtered.keys, true);
+		(filtered.hit, filtered.miss)
+	}
+
+	#[test]
+	fn test_dedup_keys_and_window() {
+		let d = dedup(json!({"keys": ["id"], "window": 10}));
+
+		let mut r = rows(json!([{"id": 1, "v": 1}, {"id": 1, "v": 2}, {"id": 2}]));
+		assert_eq!(sunk(&d, &mut r, 0), (1, 2));
+		assert_eq!(r.len(), 2);
+
+		let mut r = rows(json!([{"id": 1, "v": 3}]));
+		assert_eq!(sunk(&d, &mut r, 10_000), (1, 0));
+		// seen out of window
+		let mut r = rows(json!([{"id": 1, "v": 3}]));
+		assert_eq!(sunk(&d, &mut r, 10_001), (0, 1));
+	}
+
+	#[test]
+	fn test_dedup_row_and_capacity() {
+		let d = dedup(json!({"capacity": 2}));
+
+		let mut r = rows(json!([{"id": 1}, {"id": 1, "v": 1}, {"id": 1}]));
+		assert_eq!(sunk(&d, &mut r, 0), (1, 2));
+		// two new rows sunk evict both seen rows
+		let mut r = rows(json!([{"id": 2}, {"id": 3}]));
+		assert_eq!(sunk(&d, &mut r, 0), (0, 2));
+		let mut r = rows(json!([{"id": 1}]));
+		assert_eq!(sunk(&d, &mut r, 0), (0, 1));
+
+		// redelivered row with a new ingest time
+		let d = dedup(json!({})).with_skip(vec!["ingest_at".to_owned()]);
+		let mut r = rows(json!([{"id": 1, "ingest_at": 1}, {"id": 1, "ingest_at": 2}]));
+		assert_eq!(sunk(&d, &mut r, 0), (1, 1));
+	}
+
+	#[tokio::test]
+	async fn test_dedup_snapshot() {
+		let dir = std::env::temp_dir().join(format!("dedup_{}", std::process::id()));
+		init_snapshot_dir(&DedupConf { snapshot_dir: dir.to_str().unwrap().to_owned() });
+		let conf = json!({"keys": ["id"], "snapshot": true});
+
+		let d = dedup(conf.clone());
+		let mut r = rows(json!([{"id": 1}, {"id": 2}]));
+		let now = chrono::Utc::now().timestamp_millis();
+		assert_eq!(sunk(&d, &mut r, now), (0, 2));
+		d.snapshot().await.unwrap();
+		assert!(dir.join("7.json").exists());
+
+		let d = dedup(conf);
+		let mut r = rows(json!([{"id": 1}, {"id": 3}]));
+		assert_eq!(sunk(&d, &mut r, now), (1, 1));
+		let _ = std::fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn test_dedup_failed_sink() {
+		let d = dedup(json!({"keys": ["id"]}));
+		let mut r = rows(json!([{"id": 1}]));
+		let failed = d.filter(&mut r, 0);
+		assert_eq!(failed.miss, 1);
+		// pending row is a duplicate until settled
+		let mut r = rows(json!([{"id": 1}]));
+		assert_eq!(d.filter(&mut r, 0).hit, 1);
+
+		// sinker failed, the redelivered row is not dropped
+		d.settle(failed.keys, false);
+		let mut r = rows(json!([{"id": 1}]));
+		assert_eq!(sunk(&d, &mut r, 0), (0, 1));
+		assert_eq!(r.len(), 1);
+		let mut r = rows(json!([{"id": 1}]));
+		assert_eq!(sunk(&d, &mut r, 0), (1, 0));
+	}
+
+	#[tokio::test]
+	async fn test_dedup_redelivered() -> anyhow::Result<()> {
+		let d = dedup(json!({"keys": ["id"]}));
+		let (s, r) = mpsc::channel(4);
+		let (sink, mut out) = mpsc::channel(4);
+		let run = tokio::spawn(async move { d.run(r, sink, |_, _| {}).await });
+
+		let (acks, mut source) = mpsc::unbounded_channel();
+		let msg = |id: &str| {
+			CoreMsg::default()
+				.with_result(rows(json!([{"id": 1}])))
+				.with_ack(Some(Ack::new(id.to_owned(), acks.clone())))
+		};
+		let acked = |id: &str, kind| Some(Acked { id: id.to_owned(), kind });
+
+		// sinker failed the write
+		s.send(msg("1")).await?;
+		drop(out.recv().await);
+		assert_eq!(source.recv().await, acked("1", AckKind::Retry));
+
+		// redelivered row is not dropped
+		s.send(msg("1")).await?;
+		let sunk = out.recv().await.unwrap();
+		assert_eq!(sunk.result.len(), 1);
+		sunk.done();
+		assert_eq!(source.recv().await, acked("1", AckKind::Done));
+
+		// a duplicate of the sunk row
+		s.send(msg("2")).await?;
+		assert_eq!(source.recv().await, acked("2", AckKind::Done));
+		assert!(out.try_recv().is_err());
+
+		drop(s);
+		run.await??;
+		Ok(())
+	}
+}
@@ -6,6 +6,7 @@ use sqlx::MySqlPool;
 
 use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use tokio::sync::mpsc;
@@ -18,7 +19,9 @@ use tracing::warn;
 use tracing::Instrument;
 use tracing::Level;
 
-use crate::biz::link::sink::get_sinker;
+use crate::biz::link::pipe;
+use crate::biz::link::sink::batch::SinkErrors;
+use crate::biz::link::sink::fanout::FanoutSinker;
 use crate::biz::link::sink::Sinker;
 use crate::biz::link::source::get_source;
 use crate::biz::link::source::Source;
@@ -31,47 +34,28 @@ use crate::core::CoreMsg;
 use crate::errcode;
 
 use crate::model::task::TaskInfo;
+use crate::model::task::TaskMeta;
 use crate::model::task::TaskStatus;
 use crate::model::task_log::TaskLog;
 
 use crate::types::JsonParserOpt;
 use crate::util::from_val;
 
-use lepumk::ani::JsonParser;
+use lepumk::ani::error::ParseError;
 
-use super::link::sink::SinkerEnum;
+use super::aggregate::Aggregator;
+use super::dedup::Dedup;
 use super::link::source::SourceEnum;
 use super::task_manger::contains_task;
+use super::throttle::RateLimiter;
+use super::throttle::Sampler;
+use super::transform::MsgMeta;
+use super::transform::Pipeline;
 
 const INTERVAL: u64 = 300;
 
-#[derive(Debug, Deserialize)]
-struct SinkArg {
-	name: String,
-	val: serde_json::Value,
-}
-
-impl SinkArg {
-	fn new(val: &serde_json::Value) -> anyhow::Result<Self> {
-		let s = from_val(val).with_context(|| {
-			format!(
-				"build {} value {:?}",
-				std::any::type_name::<Self>(),
-				serde_json::json!(val).to_string()
-			)
-		})?;
-		Ok(s)
-	}
-}
-
-impl SinkArg {
-	fn get_name(&self) -> &str {
-		&self.name
-	}
-	fn get_val(&self) -> &serde_json::Value {
-		&self.val
-	}
-}
+// seconds between checks of idle aggregate windows
+const AGGREGATE_TICK: u64 = 1;
 
 #[derive(Debug, Deserialize)]
 struct SourceArg {
@@ -100,29 +84,83 @@ impl SourceArg {
 	}
 }
 
+// task counter, heartbeat flush them into task_info
+#[derive(Default)]
+struct TaskCounter {
+	handle_num: AtomicI64,
+	handle_err: AtomicI64,
+	handle_type_err: AtomicI64,
+	dedup_hit: AtomicI64,
+	dedup_miss: AtomicI64,
+	throttled: AtomicI64,
+	sampled_out: AtomicI64,
+	// rows failed in sinks, count as handle_err
+	sink_err: SinkErrors,
+}
+
+impl TaskCounter {
+	// take counter increment and reset to zero
+	fn take(&self) -> TaskMeta {
+		TaskMeta {
+			handle_num: self.handle_num.swap(0, Ordering::Relaxed),
+			handle_err: self.handle_err.swap(0, Ordering::Relaxed) + self.sink_err.take(),
+			handle_type_err: self.handle_type_err.swap(0, Ordering::Relaxed),
+			dedup_hit: self.dedup_hit.swap(0, Ordering::Relaxed),
+			dedup_miss: self.dedup_miss.swap(0, Ordering::Relaxed),
+			throttled: self.throttled.swap(0, Ordering::Relaxed),
+			sampled_out: self.sampled_out.swap(0, Ordering::Relaxed),
+		}
+	}
+}
+
 pub struct Tasking {
 	// todo
-	sink: SinkerEnum,
+	sink: FanoutSinker,
 	source: SourceEnum,
 	task: TaskInfo,
-	handle_num: AtomicI64,
-	handle_err: AtomicI64,
+	pipeline: Pipeline,
+	rate_limit: Option<RateLimiter>,
+	sample: Option<Sampler>,
+	dedup: Option<Dedup>,
+	// window aggregate, only touched by aggregate_msg
+	aggregate: Option<Mutex<Aggregator>>,
+	counter: TaskCounter,
 }
 
 impl Tasking {
 	fn new(task: TaskInfo) -> anyhow::Result<Tasking> {
 		info!("build task scheduler");
-		let sink_arg = SinkArg::new(&task.dst_config)?;
-		let sink = get_sinker(sink_arg.get_name(), sink_arg.get_val())?;
+		let counter = TaskCounter::default();
+		let mut sink = FanoutSinker::new(&task.dst_config)?;
+		sink.bind_errors(counter.sink_err.clone());
 		let source_arg = SourceArg::new(&task.src_config)?;
 		let source = get_source(source_arg.get_name(), source_arg.get_val())?;
-		Ok(Self {
-			sink,
-			source,
-			task,
-			handle_num: AtomicI64::new(0),
-			handle_err: AtomicI64::new(0),
-		})
+		let opt = from_val::<JsonParserOpt>(&task.parser_config)
+			.with_context(|| format!("build json parser opt error {:?}", task.parser_config))?;
+		let pipeline = opt.to_pipeline()?;
+		let rate_limit = opt.rate_limit.as_ref().map(RateLimiter::new).transpose()?;
+		let injected = opt.transform.timestamp.as_ref().map(|t| t.injected()).unwrap_or_default();
+		let sample = opt
+			.sample
+			.as_ref()
+			.map(|conf| Sampler::new(conf).map(|sample| sample.with_skip(injected.clone())))
+			.transpose()?;
+		let dedup = opt
+			.dedup
+			.as_ref()
+			.map(|conf| Dedup::new(conf, task.id).map(|dedup| dedup.with_skip(injected)))
+			.transpose()?;
+		let aggregate = opt.aggregate.as_ref().map(Aggregator::new).transpose()?.map(Mutex::new);
+		Ok(Self { sink, source, task, pipeline, rate_limit, sample, dedup, aggregate, counter })
+	}
+}
+
+impl Tasking {
+	// build and load lookup tables
+	async fn build(task: TaskInfo, conn: &MySqlPool) -> anyhow::Result<Tasking> {
+		let tasking = Tasking::new(task)?;
+		tasking.pipeline.load(conn).await?;
+		Ok(tasking)
 	}
 }
 
@@ -136,7 +174,7 @@ impl Tasking {
 
 		let parent = tracing::Span::none();
 		let span = tracing::span!(parent:&parent,Level::ERROR,module_path!(),"task_id"=task.id);
-		let mut tasking = match Tasking::new(task.clone()) {
+		let mut tasking = match Tasking::build(task.clone(), &conn).await {
 			Ok(tasking) => tasking,
 			Err(err) => {
 				error!("build task error {:?}", err);
@@ -167,29 +205,35 @@ impl Tasking {
 		// build channel
 		let (s1, r1) = tokio::sync::mpsc::channel(6);
 		let (s2, r2) = tokio::sync::mpsc::channel(6);
+		let (s3, r3) = tokio::sync::mpsc::channel(6);
+		let (s4, r4) = tokio::sync::mpsc::channel(6);
 		self.task.status = TaskStatus::Running.get_status();
 
 		let _ = TaskInfo::update_task(&conn, &mut self.task).await;
-		let parser = from_val::<JsonParserOpt>(&self.task.parser_config)
-			.with_context(|| format!("build json parser opt error {:?}", self.task.parser_config))?
-			.to_parser();
-
 		let (_, mut handle) = tokio_context::context::Context::new();
 		let mut ctx = handle.spawn_ctx();
 		add_task(self.task.id, handle);
+		self.attach_link(&conn);
+		self.pipeline.refresh(&conn);
 		let res = tokio::select! {
 			_ = ctx.done() => {
 
 				Err(anyhow::anyhow!("use cancel task"))
 			},
-			res = self.sink.sink(r2) => {
+			res = self.sink.sink(r4) => {
 				//
 				res
 			},
 			res = self.source.source(s1) => {
 				res
 			},
-			res= self.handle_msg(parser,r1,s2) => {
+			res= self.handle_msg(r1,s2) => {
+				res
+			},
+			res = self.dedup_msg(r2, s3) => {
+				res
+			},
+			res = self.aggregate_msg(r3, s4) => {
 				res
 			},
 			res = self.update_task_heartbeat(conn.clone(), self.task.id) => {
@@ -199,19 +243,17 @@ impl Tasking {
 
 		// task has cancel or error
 		remove_task(self.task.id);
+		for id in pipe::detach(self.task.id) {
+			if contains_task(id) {
+				warn!("stop upstream task {} with task {}", id, self.task.id);
+				remove_task(id);
+			}
+		}
 		warn!("stop__task {res:?} {}", self.task.id);
 
-		let add_handle_num = self
-			.handle_num
-			.fetch_add(-self.handle_num.fetch_add(0, Ordering::Relaxed), Ordering::Relaxed);
-
-		//
-		let add_handle_err = self
-			.handle_err
-			.fetch_add(-self.handle_err.fetch_add(0, Ordering::Relaxed), Ordering::Relaxed);
-
 		// update task status
-		let _ = TaskInfo::update_meta(&conn, self.task.id, add_handle_num, add_handle_err).await;
+		let _ = TaskInfo::update_meta(&conn, self.task.id, self.counter.take()).await;
+		self.snapshot_dedup().await;
 
 		match res {
 			Ok(res) => {
@@ -254,18 +296,10 @@ impl Tasking {
 		// ...
 		loop {
 			let _i = ticker.tick().await;
-			// ..
-			let add_handle_num = self
-				.handle_num
-				.fetch_add(-self.handle_num.fetch_add(0, Ordering::Relaxed), Ordering::Relaxed);
-
-			//
-			let add_handle_err = self
-				.handle_err
-				.fetch_add(-self.handle_err.fetch_add(0, Ordering::Relaxed), Ordering::Relaxed);
 
 			// update task status
-			let res = TaskInfo::update_meta(&conn, id, add_handle_num, add_handle_err).await;
+			let res = TaskInfo::update_meta(&conn, id, self.counter.take()).await;
+			self.snapshot_dedup().await;
 			match res {
 				Ok(_) => {
 					// todo
@@ -289,23 +323,46 @@ impl Tasking {
 impl Tasking {
 	async fn handle_msg(
 		&self,
-		p: JsonParser,
 		mut receiver: mpsc::Receiver<CoreMsg>,
 		sender: mpsc::Sender<CoreMsg>,
 	) -> anyhow::Result<()> {
 		while let Some(mut msg) = receiver.recv().await {
-			self.handle_num.fetch_add(1, Ordering::Relaxed);
-			match p.run(msg.get_raw_msg()).await {
-				Ok(result) => {
-					msg.result = result;
+			// source wait on the full channel while throttled
+			if let Some(rate_limit) = &self.rate_limit {
+				if rate_limit.acquire().await {
+					self.counter.throttled.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+			self.counter.handle_num.fetch_add(1, Ordering::Relaxed);
+			match self.pipeline.run(msg.get_raw_msg(), &MsgMeta::new(msg.timestamp)) {
+				Ok(parsed) => {
+					if parsed.failures > 0 {
+						self.counter.handle_type_err.fetch_add(1, Ordering::Relaxed);
+					}
+					msg.result = parsed.rows;
+				}
+				Err(err) if matches!(err.downcast_ref(), Some(ParseError::Type(_))) => {
+					error!("coerce msg {} error {:?}", msg.get_raw_msg(), err);
+					self.counter.handle_type_err.fetch_add(1, Ordering::Relaxed);
+					msg.reject();
+					continue;
 				}
 				Err(err) => {
 					error!("handle msg {} error {:?}", msg.get_raw_msg(), err);
-					self.handle_err.fetch_add(1, Ordering::Relaxed);
+					self.counter.handle_err.fetch_add(1, Ordering::Relaxed);
+					msg.reject();
 					continue;
 				}
 			};
-			msg = msg.with_raw_keys(p.0.get_keys().clone());
+			if let Some(sample) = &self.sample {
+				let dropped = sample.filter(&mut msg.result);
+				self.counter.sampled_out.fetch_add(dropped, Ordering::Relaxed);
+				if msg.result.is_empty() {
+					msg.done();
+					continue;
+				}
+			}
+			msg = msg.with_raw_keys(self.pipeline.parser().0.get_keys().clone());
 			sender.send(msg).await?;
 		}
 
@@ -313,3 +370,116 @@ impl Tasking {
 		Ok(())
 	}
 }
+
+impl Tasking {
+	// drop duplicate rows between handle_msg and sinker, pass through without dedup
+	async fn dedup_msg(
+		&self,
+		mut receiver: mpsc::Receiver<CoreMsg>,
+		sender: mpsc::Sender<CoreMsg>,
+	) -> anyhow::Result<()> {
+		let Some(dedup) = &self.dedup else {
+			while let Some(msg) = receiver.recv().await {
+				sender.send(msg).await?;
+			}
+			info!("close dedup sender");
+			return Ok(());
+		};
+		dedup
+			.run(receiver, sender, |hit, miss| {
+				self.counter.dedup_hit.fetch_add(hit, Ordering::Relaxed);
+				self.counter.dedup_miss.fetch_add(miss, Ordering::Relaxed);
+			})
+			.await
+	}
+
+	async fn snapshot_dedup(&self) {
+		if let Some(dedup) = &self.dedup {
+			if let Err(err) = dedup.snapshot().await {
+				error!("snapshot dedup task {} error {:?}", self.task.id, err);
+			}
+		}
+	}
+}
+
+impl Tasking {
+	// aggregate rows into windows, send closed windows to sinker, pass through without aggregate
+	async fn aggregate_msg(
+		&self,
+		mut receiver: mpsc::Receiver<CoreMsg>,
+		sender: mpsc::Sender<CoreMsg>,
+	) -> anyhow::Result<()> {
+		let Some(aggregate) = &self.aggregate else {
+			while let Some(msg) = receiver.recv().await {
+				sender.send(msg).await?;
+			}
+			return Ok(());
+		};
+
+		let mut tick = tokio::time::interval(Duration::from_secs(AGGREGATE_TICK));
+		let mut received = false;
+		loop {
+			let msg = tokio::select! {
+				msg = receiver.recv() => {
+					let Some(msg) = msg else {
+						break;
+					};
+					received = true;
+					let mut agg = aggregate.lock().unwrap();
+					agg.add_msg(msg);
+					agg.flush(false)
+				}
+				_ = tick.tick() => {
+					let mut agg = aggregate.lock().unwrap();
+					// no message in the last tick
+					if !std::mem::take(&mut received) {
+						agg.idle(AGGREGATE_TICK as i64 * 1000);
+					}
+					agg.flush(false)
+				}
+			};
+			if let Some(msg) = msg {
+				sender.send(msg).await?;
+			}
+		}
+
+		// source finished, flush the open windows
+		let (msg, dropped) = {
+			let mut agg = aggregate.lock().unwrap();
+			(agg.flush(true), agg.dropped)
+		};
+		info!("close aggregate sender, late or without time rows {}", dropped);
+		if let Some(msg) = msg {
+			sender.send(msg).await?;
+		}
+		Ok(())
+	}
+}
+
+impl Tasking {
+	// bind http and binlog sources, record pipe dependency between tasks
+	fn attach_link(&self, conn: &MySqlPool) {
+		let sources = match &self.source {
+			SourceEnum::HttpSource(source) => {
+				source.bind(self.task.id);
+				vec![]
+			}
+			SourceEnum::HttpPollSource(source) => {
+				source.bind(self.task.id, conn.clone());
+				vec![]
+			}
+			SourceEnum::MySqlBinlogSource(source) => {
+				source.bind(self.task.id, conn.clone());
+				vec![]
+			}
+			SourceEnum::PipeSource(source) => {
+				vec![(source.get_name().to_owned(), source.get_cascade())]
+			}
+			_ => vec![],
+		};
+		let sinks = self.sink.pipes();
+		if !sources.is_empty() || !sinks.is_empty() {
+			pipe::attach(self.task.id, &sources, &sinks);
+		}
+	}
+}
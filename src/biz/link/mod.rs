@@ -1,2 +1,3 @@
+pub mod pipe;
 pub mod sink;
 pub mod source;
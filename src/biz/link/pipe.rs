@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use tokio::sync::mpsc;
+
+use tracing::info;
+use tracing::warn;
+
+use crate::core::CoreMsg;
+
+// buffered messages of a pipe, upstream sink wait when it is full
+const PIPE_SIZE: usize = 1024;
+
+lazy_static! {
+	static ref PIPES: Mutex<HashMap<String, Arc<Pipe>>> = Mutex::new(HashMap::new());
+}
+
+// in-process channel between tasks, upstream task sink into and downstream task source from
+pub struct Pipe {
+	name: String,
+	sender: mpsc::Sender<CoreMsg>,
+	// keep in pipe, buffered messages survive downstream restart
+	receiver: tokio::sync::Mutex<mpsc::Receiver<CoreMsg>>,
+	tasks: Mutex<PipeTasks>,
+}
+
+#[derive(Default)]
+struct PipeTasks {
+	// upstream task ids
+	writers: HashSet<i64>,
+	// downstream task id and stop upstream with it
+	reader: Option<(i64, bool)>,
+}
+
+impl Pipe {
+	pub fn sender(&self) -> &mpsc::Sender<CoreMsg> {
+		&self.sender
+	}
+
+	// only one source read a pipe at the same time
+	pub fn receiver(&self) -> anyhow::Result<tokio::sync::MutexGuard<'_, mpsc::Receiver<CoreMsg>>> {
+		self.receiver
+			.try_lock()
+			.map_err(|_| anyhow::anyhow!("pipe {} has source already", self.name))
+	}
+}
+
+// get or create pipe by name
+pub fn pipe(name: &str) -> Arc<Pipe> {
+	let mut pipes = PIPES.lock().unwrap();
+	pipes
+		.entry(name.to_owned())
+		.or_insert_with(|| {
+			let (sender, receiver) = mpsc::channel(PIPE_SIZE);
+			Arc::new(Pipe {
+				name: name.to_owned(),
+				sender,
+				receiver: tokio::sync::Mutex::new(receiver),
+				tasks: Mutex::default(),
+			})
+		})
+		.clone()
+}
+
+// record task pipes, warn pipe without the other side
+pub fn attach(task_id: i64, sources: &[(String, bool)], sinks: &[String]) {
+	for (name, cascade) in sources {
+		let pipe = pipe(name);
+		let mut tasks = pipe.tasks.lock().unwrap();
+		if let Some((reader, _)) = tasks.reader {
+			warn!("pipe {} source task {} replaced by task {}", name, reader, task_id);
+		}
+		tasks.reader = Some((task_id, *cascade));
+		if tasks.writers.is_empty() {
+			warn!("pipe {} source task {} start without upstream task", name, task_id);
+		}
+	}
+	for name in sinks {
+		let pipe = pipe(name);
+		let mut tasks = pipe.tasks.lock().unwrap();
+		tasks.writers.insert(task_id);
+		if tasks.reader.is_none() {
+			warn!(
+				"pipe {} sink task {} start without downstream task, wait when full",
+				name, task_id
+			);
+		}
+	}
+}
+
+// remove task from pipes, return upstream tasks stop with it
+pub fn detach(task_id: i64) -> Vec<i64> {
+	let pipes = PIPES.lock().unwrap();
+	let mut cascade = vec![];
+	for pipe in pipes.values() {
+		let mut tasks = pipe.tasks.lock().unwrap();
+		tasks.writers.remove(&task_id);
+		match tasks.reader {
+			Some((reader, true)) if reader == task_id => {
+				info!("pipe {} stop upstream tasks {:?}", pipe.name, tasks.writers);
+				cascade.extend(tasks.writers.iter().copied());
+				tasks.reader = None;
+			}
+			Some((reader, false)) if reader == task_id => {
+				if !tasks.writers.is_empty() {
+					warn!("pipe {} upstream tasks {:?} keep running", pipe.name, tasks.writers);
+				}
+				tasks.reader = None;
+			}
+			_ => {}
+		}
+	}
+	cascade
+}
+
+#[cfg(test)]
+mod my_test {
+	use super::attach;
+	use super::detach;
+
+	#[test]
+	fn test_detach() {
+		attach(9001, &[], &["pipe_test_a".to_owned()]);
+		attach(9002, &[("pipe_test_a".to_owned(), true)], &["pipe_test_b".to_owned()]);
+		attach(9003, &[("pipe_test_b".to_owned(), false)], &[]);
+
+		// downstream without cascade
+		assert!(detach(9003).is_empty());
+		assert_eq!(detach(9002), vec![9001]);
+		assert!(detach(9001).is_empty());
+	}
+}
@@ -0,0 +1,344 @@
+use std::future::Future;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use tracing::warn;
+
+use lepumk::ani::Row;
+
+use crate::core::Ack;
+use crate::core::CoreMsg;
+
+// rows failed in sinker, task flush them into handle_err
+#[derive(Debug, Clone, Default)]
+pub struct SinkErrors {
+	count: Arc<AtomicI64>,
+	// failed rows go back to fan-out with the sink index
+	failed: Option<(usize, mpsc::UnboundedSender<(usize, CoreMsg)>)>,
+}
+
+impl SinkErrors {
+	pub fn with_failed(
+		self,
+		index: usize,
+		failed: mpsc::UnboundedSender<(usize, CoreMsg)>,
+	) -> Self {
+		Self { failed: Some((index, failed)), ..self }
+	}
+
+	pub fn add(&self, n: i64) {
+		self.count.fetch_add(n, Ordering::Relaxed);
+	}
+
+	pub fn take(&self) -> i64 {
+		self.count.swap(0, Ordering::Relaxed)
+	}
+
+	// rows failed after retries, fan-out policy handle them,
+	// else the source messages are delivered again
+	pub fn fail(&self, msg: CoreMsg) {
+		self.add(msg.result.len() as i64);
+		if let Some((index, failed)) = &self.failed {
+			let _ = failed.send((*index, msg));
+		}
+	}
+}
+
+// flush rows when one of count, bytes and linger reach
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchConfig {
+	#[serde(default = "default_batch_rows")]
+	pub batch_rows: usize,
+	// json bytes of rows
+	#[serde(default = "default_batch_bytes")]
+	pub batch_bytes: usize,
+	// millis the first row wait
+	#[serde(default = "default_linger_ms")]
+	pub linger_ms: u64,
+}
+
+fn default_batch_rows() -> usize {
+	500
+}
+
+fn default_batch_bytes() -> usize {
+	1 << 20
+}
+
+fn default_linger_ms() -> u64 {
+	1000
+}
+
+impl Default for BatchConfig {
+	fn default() -> Self {
+		Self {
+			batch_rows: default_batch_rows(),
+			batch_bytes: default_batch_bytes(),
+			linger_ms: default_linger_ms(),
+		}
+	}
+}
+
+// row and its json
+pub struct Batch {
+	pub rows: Vec<Row>,
+	pub lines: Vec<String>,
+	bytes: usize,
+	// copies of source message acks, settled by the flush
+	acks: Vec<Ack>,
+}
+
+impl Batch {
+	fn new() -> Self {
+		Self { rows: vec![], lines: vec![], bytes: 0, acks: vec![] }
+	}
+
+	fn push(&mut self, row: Row) {
+		let line = serde_json::json!(row).to_string();
+		self.bytes += line.len();
+		self.rows.push(row);
+		self.lines.push(line);
+	}
+
+	fn is_full(&self, conf: &BatchConfig) -> bool {
+		self.rows.len() >= conf.batch_rows || self.bytes >= conf.batch_bytes
+	}
+
+	pub fn len(&self) -> usize {
+		self.rows.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rows.is_empty()
+	}
+
+	// rows are written
+	pub fn done(self) {
+		self.acks.into_iter().for_each(Ack::done);
+	}
+
+	// rows and acks of a failed batch
+	pub fn into_msg(self) -> CoreMsg {
+		CoreMsg::default().with_result(self.rows).with_acks(self.acks)
+	}
+}
+
+// receive rows into batches until the receiver close, flush error stop the sinker,
+// flush settle the batch by done or SinkErrors::fail, a dropped batch is retried by source
+pub async fn run_batches<F, Fut>(
+	mut r: mpsc::Receiver<CoreMsg>,
+	conf: &BatchConfig,
+	mut flush: F,
+) -> anyhow::Result<()>
+where
+	F: FnMut(Batch) -> Fut,
+	Fut: Future<Output = anyhow::Result<()>>,
+{
+	let linger = Duration::from_millis(conf.linger_ms);
+	let mut batch = Batch::new();
+	let mut deadline = Instant::now();
+	loop {
+		tokio::select! {
+			msg = r.recv() => {
+				let Some(msg) = msg else {
+					break;
+				};
+				// every batch with rows of the msg hold a copy of its acks
+				let mut attached = false;
+				for row in msg.result {
+					if batch.is_empty() {
+						deadline = Instant::now() + linger;
+					}
+					if !attached {
+						batch.acks.extend(msg.acks.iter().cloned());
+						attached = true;
+					}
+					batch.push(row);
+					if batch.is_full(conf) {
+						flush(std::mem::replace(&mut batch, Batch::new())).await?;
+						attached = false;
+					}
+				}
+				// handed over to the batches
+				msg.acks.into_iter().for_each(Ack::done);
+			},
+			_ = tokio::time::sleep_until(deadline), if !batch.is_empty() => {
+				flush(std::mem::replace(&mut batch, Batch::new())).await?;
+			},
+		}
+	}
+	if !batch.is_empty() {
+		flush(batch).await?;
+	}
+	Ok(())
+}
+
+// retry of a failed batch, backoff double every attempt
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetryConfig {
+	#[serde(default = "default_max_retries")]
+	pub max_retries: u32,
+	// millis of the first retry
+	#[serde(default = "default_retry_backoff_ms")]
+	pub retry_backoff_ms: u64,
+}
+
+fn default_max_retries() -> u32 {
+	3
+}
+
+fn default_retry_backoff_ms() -> u64 {
+	500
+}
+
+impl RetryConfig {
+	// wait of attempt from 0, max 30 seconds
+	pub fn backoff(&self, attempt: u32) -> Duration {
+		Duration::from_millis(
+			self.retry_backoff_ms.saturating_mul(1 << attempt.min(16)).min(30_000),
+		)
+	}
+}
+
+// send request until success, retry 5xx, 429 and request error, other status fail at once
+pub async fn send_retry<F>(
+	name: &str,
+	retry: &RetryConfig,
+	req: F,
+) -> anyhow::Result<reqwest::Response>
+where
+	F: Fn() -> reqwest::RequestBuilder,
+{
+	let mut attempt = 0;
+	loop {
+		let err = match req().send().await {
+			Ok(resp) if resp.status().is_success() => return Ok(resp),
+			Ok(resp) => {
+				let status = resp.status();
+				let body = resp.text().await.unwrap_or_default();
+				let err = anyhow::anyhow!("status {} {}", status, body.trim());
+				if !status.is_server_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+					return Err(err);
+				}
+				err
+			}
+			Err(err) => err.into(),
+		};
+		if attempt >= retry.max_retries {
+			return Err(err.context(format!("{} retry {} times", name, attempt)));
+		}
+		let wait = retry.backoff(attempt);
+		warn!("{} retry {} after {:?} {:?}", name, attempt + 1, wait, err);
+		tokio::time::sleep(wait).await;
+		attempt += 1;
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::run_batches;
+	use super::BatchConfig;
+	use super::SinkErrors;
+	use crate::core::Ack;
+	use crate::core::AckKind;
+	use crate::core::CoreMsg;
+
+	#[tokio::test]
+	async fn test_batches() -> anyhow::Result<()> {
+		let conf = BatchConfig { batch_rows: 3, batch_bytes: 1 << 20, linger_ms: 10 };
+		let (s, r) = mpsc::channel(6);
+		let rows = |n: i64| {
+			(0..n).map(|i| serde_json::from_value(json!({"i": i})).unwrap()).collect::<Vec<_>>()
+		};
+		let sender = tokio::spawn(async move {
+			s.send(CoreMsg::default().with_result(rows(4))).await.unwrap();
+			// linger flush the single row
+			tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+			s.send(CoreMsg::default().with_result(rows(2))).await.unwrap();
+		});
+
+		let mut sizes = vec![];
+		run_batches(r, &conf, |batch| {
+			sizes.push(batch.len());
+			async { Ok(()) }
+		})
+		.await?;
+		sender.await?;
+		assert_eq!(sizes, vec![3, 1, 2]);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_batch_ack() -> anyhow::Result<()> {
+		let conf = BatchConfig { batch_rows: 2, batch_bytes: 1 << 20, linger_ms: 1000 };
+		let (s, r) = mpsc::channel(6);
+		let (acks, mut acked) = mpsc::unbounded_channel();
+		for id in ["1", "2"] {
+			let rows = vec![serde_json::from_value(json!({"id": id}))?];
+			let ack = Ack::new(id.to_owned(), acks.clone());
+			s.send(CoreMsg::default().with_result(rows).with_ack(Some(ack))).await?;
+		}
+		drop(s);
+
+		run_batches(r, &conf, |batch| {
+			// acked after flush
+			assert!(acked.try_recv().is_err());
+			batch.done();
+			async { Ok(()) }
+		})
+		.await?;
+		assert_eq!(acked.recv().await.map(|a| a.id).as_deref(), Some("1"));
+		assert_eq!(
+			acked.recv().await.map(|a| (a.id, a.kind)),
+			Some(("2".to_owned(), AckKind::Done))
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_batch_fail_retry() -> anyhow::Result<()> {
+		let conf = BatchConfig { batch_rows: 2, batch_bytes: 1 << 20, linger_ms: 1000 };
+		let (s, r) = mpsc::channel(6);
+		let (acks, mut acked) = mpsc::unbounded_channel();
+		// rows of msg 1 span the failed first batch and the written second batch
+		let rows = |ids: &[i64]| {
+			ids.iter().map(|id| serde_json::from_value(json!({"id": id})).unwrap()).collect()
+		};
+		let ack = |id: &str| Some(Ack::new(id.to_owned(), acks.clone()));
+		s.send(CoreMsg::default().with_result(rows(&[1, 2, 3])).with_ack(ack("1"))).await?;
+		s.send(CoreMsg::default().with_result(rows(&[4])).with_ack(ack("2"))).await?;
+		drop(s);
+
+		let errors = SinkErrors::default();
+		let mut flushed = 0;
+		run_batches(r, &conf, |batch| {
+			flushed += 1;
+			if flushed == 1 {
+				errors.fail(batch.into_msg());
+			} else {
+				batch.done();
+			}
+			async { Ok(()) }
+		})
+		.await?;
+		assert_eq!(errors.take(), 2);
+		let mut kinds = [acked.recv().await.unwrap(), acked.recv().await.unwrap()];
+		kinds.sort_by(|a, b| a.id.cmp(&b.id));
+		assert_eq!(
+			kinds.iter().map(|a| a.kind).collect::<Vec<_>>(),
+			[AckKind::Retry, AckKind::Done]
+		);
+		Ok(())
+	}
+}
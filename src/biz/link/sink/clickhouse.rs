@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use anyhow::Context;
+
+use serde::Deserialize;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::instrument;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::run_batches;
+use super::batch::send_retry;
+use super::batch::Batch;
+use super::batch::BatchConfig;
+use super::batch::RetryConfig;
+use super::batch::SinkErrors;
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct ClickHouseSinkArg {
+	// http interface like http://127.0.0.1:8123
+	url: String,
+	// table or db.table
+	table: String,
+	#[serde(default)]
+	user: Option<String>,
+	#[serde(default)]
+	password: Option<String>,
+	// only these row keys are inserted, empty insert all keys
+	#[serde(default)]
+	columns: Vec<String>,
+	// send insert_deduplication_token of batch, retried batch insert once
+	#[serde(default)]
+	dedup_token: bool,
+	#[serde(flatten)]
+	batch: BatchConfig,
+	// retry on 5xx, 429 and timeout
+	#[serde(flatten)]
+	retry: RetryConfig,
+	// request timeout seconds
+	#[serde(default = "default_timeout")]
+	timeout: u64,
+}
+
+fn default_timeout() -> u64 {
+	30
+}
+
+// quote identifier, db.table quote both parts
+fn quote(ident: &str) -> String {
+	ident
+		.split('.')
+		.map(|part| format!("`{}`", part.replace('`', "\\`")))
+		.collect::<Vec<_>>()
+		.join(".")
+}
+
+// insert batches by INSERT ... FORMAT JSONEachRow, failed batch count into handle_err
+pub struct ClickHouseSinker {
+	arg: ClickHouseSinkArg,
+	// insert statement send as query param
+	query: String,
+	errors: SinkErrors,
+}
+
+impl ClickHouseSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<ClickHouseSinker> {
+		let arg: ClickHouseSinkArg = from_val(val)?;
+		reqwest::Url::parse(&arg.url)
+			.with_context(|| format!("clickhouse sink url {}", arg.url))?;
+		if arg.table.is_empty() {
+			anyhow::bail!("clickhouse sink table is empty");
+		}
+		let columns = if arg.columns.is_empty() {
+			String::new()
+		} else {
+			format!(" ({})", arg.columns.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", "))
+		};
+		let query = format!("INSERT INTO {}{} FORMAT JSONEachRow", quote(&arg.table), columns);
+		Ok(Self { arg, query, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	fn client(&self) -> anyhow::Result<reqwest::Client> {
+		let mut headers = reqwest::header::HeaderMap::new();
+		if let Some(user) = &self.arg.user {
+			headers.insert("X-ClickHouse-User", reqwest::header::HeaderValue::from_str(user)?);
+		}
+		if let Some(password) = &self.arg.password {
+			let mut val = reqwest::header::HeaderValue::from_str(password)?;
+			val.set_sensitive(true);
+			headers.insert("X-ClickHouse-Key", val);
+		}
+		let client = reqwest::Client::builder()
+			.default_headers(headers)
+			.timeout(Duration::from_secs(self.arg.timeout))
+			.build()?;
+		Ok(client)
+	}
+
+	// row per line, keys out of columns are dropped
+	fn body(&self, batch: &Batch) -> String {
+		let mut body = String::new();
+		for (row, line) in batch.rows.iter().zip(batch.lines.iter()) {
+			if self.arg.columns.is_empty() {
+				body.push_str(line);
+			} else {
+				let row = self
+					.arg
+					.columns
+					.iter()
+					.filter_map(|c| row.get(c).map(|val| (c.clone(), val.clone())))
+					.collect::<serde_json::Map<_, _>>();
+				body.push_str(&serde_json::Value::Object(row).to_string());
+			}
+			body.push('\n');
+		}
+		body
+	}
+
+	async fn insert(&self, client: &reqwest::Client, batch: Batch) {
+		let body = self.body(&batch);
+		let mut params = vec![("query", self.query.clone())];
+		if self.arg.dedup_token {
+			// same body same token, retry of an inserted batch is skipped by clickhouse
+			params.push(("insert_deduplication_token", hex::encode(Sha256::digest(&body))));
+		}
+		let res = send_retry(&self.arg.table, &self.arg.retry, || {
+			client.post(&self.arg.url).query(&params).body(body.clone())
+		})
+		.await;
+		match res {
+			Ok(_) => batch.done(),
+			Err(err) => {
+				error!("clickhouse sink {} rows {} error {:?}", self.arg.table, batch.len(), err);
+				self.errors.fail(batch.into_msg());
+			}
+		}
+	}
+}
+
+impl Sinker for ClickHouseSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let client = self.client()?;
+		run_batches(r, &self.arg.batch, |batch| {
+			let client = &client;
+			async move {
+				self.insert(client, batch).await;
+				Ok(())
+			}
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashMap;
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use axum::extract::Query;
+	use axum::extract::State;
+	use axum::http::HeaderMap;
+	use axum::http::StatusCode;
+	use axum::routing::post;
+	use axum::Router;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::ClickHouseSinker;
+	use crate::biz::link::sink::Sinker;
+	use crate::core::CoreMsg;
+
+	type Inserts = Arc<Mutex<Vec<(HashMap<String, String>, String)>>>;
+
+	// clickhouse stand-in, the first insert fails
+	async fn insert(
+		State(inserts): State<Inserts>,
+		Query(params): Query<HashMap<String, String>>,
+		headers: HeaderMap,
+		body: String,
+	) -> StatusCode {
+		if headers.get("X-ClickHouse-User").and_then(|v| v.to_str().ok()) != Some("default") {
+			return StatusCode::FORBIDDEN;
+		}
+		let mut inserts = inserts.lock().unwrap();
+		inserts.push((params, body));
+		if inserts.len() == 1 {
+			StatusCode::SERVICE_UNAVAILABLE
+		} else {
+			StatusCode::OK
+		}
+	}
+
+	#[tokio::test]
+	async fn test_clickhouse_sink() -> anyhow::Result<()> {
+		let inserts = Inserts::default();
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let app = Router::new().route("/", post(insert)).with_state(inserts.clone());
+		let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+		let sinker = ClickHouseSinker::new(&json!({
+			"url": format!("http://{}/", addr),
+			"table": "db.event",
+			"user": "default",
+			"columns": ["id", "name"],
+			"dedup_token": true,
+			"retry_backoff_ms": 1
+		}))?;
+		let (s, r) = mpsc::channel(1);
+		let rows = json!([{"id": 1, "name": "a", "other": 1}, {"id": 2}]);
+		s.send(CoreMsg::default().with_result(serde_json::from_value(rows)?)).await?;
+		drop(s);
+		sinker.sink(r).await?;
+
+		let inserts = inserts.lock().unwrap().clone();
+		assert_eq!(inserts.len(), 2);
+		let (params, body) = &inserts[1];
+		assert_eq!(params["query"], "INSERT INTO `db`.`event` (`id`, `name`) FORMAT JSONEachRow");
+		assert_eq!(body, "{\"id\":1,\"name\":\"a\"}\n{\"id\":2}\n");
+		// retry with the same token
+		assert_eq!(
+			params["insert_deduplication_token"],
+			inserts[0].0["insert_deduplication_token"]
+		);
+		server.abort();
+		Ok(())
+	}
+}
@@ -0,0 +1,300 @@
+use std::time::Duration;
+
+use anyhow::Context;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use tokio::sync::mpsc;
+
+use tracing::debug;
+use tracing::error;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::run_batches;
+use super::batch::send_retry;
+use super::batch::Batch;
+use super::batch::BatchConfig;
+use super::batch::RetryConfig;
+use super::batch::SinkErrors;
+use super::template::RowTemplate;
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct ElasticSinkArg {
+	// cluster address like http://127.0.0.1:9200
+	url: String,
+	// index name template like logs-{service}-{ts:%Y.%m.%d}
+	index: String,
+	// row key as document id, resent row overwrite itself
+	#[serde(default)]
+	id_field: Option<String>,
+	#[serde(default)]
+	op: BulkOp,
+	#[serde(default)]
+	user: Option<String>,
+	#[serde(default)]
+	password: Option<String>,
+	#[serde(default)]
+	api_key: Option<String>,
+	#[serde(flatten)]
+	batch: BatchConfig,
+	// retry the whole bulk on 5xx, 429 and timeout
+	#[serde(flatten)]
+	retry: RetryConfig,
+	// request timeout seconds
+	#[serde(default = "default_timeout")]
+	timeout: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BulkOp {
+	// create or replace
+	#[default]
+	Index,
+	// conflict of existing id is skipped
+	Create,
+}
+
+fn default_timeout() -> u64 {
+	30
+}
+
+// document id, string without quote
+fn id_of(val: &serde_json::Value) -> Option<String> {
+	match val {
+		serde_json::Value::Null => None,
+		serde_json::Value::String(s) => Some(s.clone()),
+		other => Some(other.to_string()),
+	}
+}
+
+// write rows by _bulk api, failed items count into handle_err
+pub struct ElasticSinker {
+	arg: ElasticSinkArg,
+	index: RowTemplate,
+	errors: SinkErrors,
+}
+
+impl ElasticSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<ElasticSinker> {
+		let arg: ElasticSinkArg = from_val(val)?;
+		reqwest::Url::parse(&arg.url).with_context(|| format!("elastic sink url {}", arg.url))?;
+		let index = RowTemplate::new(&arg.index).context("elastic sink index")?;
+		Ok(Self { arg, index, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	fn client(&self) -> anyhow::Result<reqwest::Client> {
+		let mut headers = reqwest::header::HeaderMap::new();
+		if let Some(api_key) = &self.arg.api_key {
+			let mut val = reqwest::header::HeaderValue::from_str(&format!("ApiKey {}", api_key))?;
+			val.set_sensitive(true);
+			headers.insert(reqwest::header::AUTHORIZATION, val);
+		}
+		let client = reqwest::Client::builder()
+			.default_headers(headers)
+			.timeout(Duration::from_secs(self.arg.timeout))
+			.build()?;
+		Ok(client)
+	}
+
+	// action and document lines, return index of rows sent and rows without index
+	fn body(&self, batch: &Batch) -> (String, Vec<usize>, Vec<usize>) {
+		let op = match self.arg.op {
+			BulkOp::Index => "index",
+			BulkOp::Create => "create",
+		};
+		let mut body = String::new();
+		let mut sent = vec![];
+		let mut skipped = vec![];
+		for (i, (row, line)) in batch.rows.iter().zip(batch.lines.iter()).enumerate() {
+			// index name is lowercase
+			let Some(index) = self.index.render(row).map(|index| index.to_lowercase()) else {
+				skipped.push(i);
+				continue;
+			};
+			sent.push(i);
+			let mut meta = json!({ "_index": index });
+			if let Some(id) = self.arg.id_field.as_ref().and_then(|f| row.get(f)).and_then(id_of) {
+				meta["_id"] = json!(id);
+			}
+			let mut action = serde_json::Map::new();
+			action.insert(op.to_owned(), meta);
+			body.push_str(&serde_json::Value::Object(action).to_string());
+			body.push('\n');
+			body.push_str(line);
+			body.push('\n');
+		}
+		(body, sent, skipped)
+	}
+
+	async fn bulk(&self, client: &reqwest::Client, batch: Batch) {
+		let (body, sent, mut failed) = self.body(&batch);
+		if !failed.is_empty() {
+			warn!("elastic sink {} rows without index field", failed.len());
+		}
+		if !body.is_empty() {
+			let url = format!("{}/_bulk", self.arg.url.trim_end_matches('/'));
+			let res = send_retry(&url, &self.arg.retry, || {
+				let req = client
+					.post(&url)
+					.header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+					.body(body.clone());
+				match &self.arg.user {
+					Some(user) => req.basic_auth(user, self.arg.password.as_ref()),
+					None => req,
+				}
+			})
+			.await;
+			let res = match res {
+				Ok(resp) => resp.json::<serde_json::Value>().await.map_err(anyhow::Error::from),
+				Err(err) => Err(err),
+			};
+			match res {
+				Ok(resp) => {
+					let items = self.failed_items(&resp);
+					debug!("elastic sink bulk rows {} failed {}", sent.len(), items.len());
+					failed.extend(items.into_iter().filter_map(|item| sent.get(item).copied()));
+				}
+				Err(err) => {
+					error!("elastic sink {} rows {} error {:?}", url, sent.len(), err);
+					failed.extend(sent);
+				}
+			}
+		}
+		if failed.is_empty() {
+			return batch.done();
+		}
+		let mut msg = batch.into_msg();
+		msg.result = failed.iter().map(|i| std::mem::take(&mut msg.result[*i])).collect();
+		self.errors.fail(msg);
+	}
+
+	// position of failed items in bulk response, existing id of create is not an error
+	fn failed_items(&self, resp: &serde_json::Value) -> Vec<usize> {
+		if resp["errors"] != json!(true) {
+			return vec![];
+		}
+		let items = resp["items"].as_array().map(Vec::as_slice).unwrap_or_default();
+		items
+			.iter()
+			.enumerate()
+			.filter_map(|(i, item)| {
+				item.as_object().and_then(|item| item.values().next()).map(|result| (i, result))
+			})
+			.filter(|(_, result)| {
+				let status = result["status"].as_u64().unwrap_or(0);
+				if status < 300 || (self.arg.op == BulkOp::Create && status == 409) {
+					return false;
+				}
+				warn!("elastic sink item {} error {}", status, result["error"]);
+				true
+			})
+			.map(|(i, _)| i)
+			.collect()
+	}
+}
+
+impl Sinker for ElasticSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let client = self.client()?;
+		run_batches(r, &self.arg.batch, |batch| {
+			let client = &client;
+			async move {
+				self.bulk(client, batch).await;
+				Ok(())
+			}
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use axum::extract::State;
+	use axum::routing::post;
+	use axum::Json;
+	use axum::Router;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::ElasticSinker;
+	use crate::biz::link::sink::batch::SinkErrors;
+	use crate::biz::link::sink::Sinker;
+	use crate::core::CoreMsg;
+
+	type Bodies = Arc<Mutex<Vec<String>>>;
+
+	// bulk stand-in, the document with bad fails
+	async fn bulk(State(bodies): State<Bodies>, body: String) -> Json<serde_json::Value> {
+		let items = body
+			.lines()
+			.skip(1)
+			.step_by(2)
+			.map(|doc| {
+				if doc.contains("bad") {
+					json!({"create": {"status": 400, "error": {"type": "mapper_parsing_exception"}}})
+				} else if doc.contains("dup") {
+					json!({"create": {"status": 409, "error": {"type": "version_conflict"}}})
+				} else {
+					json!({"create": {"status": 201}})
+				}
+			})
+			.collect::<Vec<_>>();
+		bodies.lock().unwrap().push(body);
+		Json(json!({"errors": true, "items": items}))
+	}
+
+	#[tokio::test]
+	async fn test_elastic_sink() -> anyhow::Result<()> {
+		let bodies = Bodies::default();
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let app = Router::new().route("/_bulk", post(bulk)).with_state(bodies.clone());
+		let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+		let mut sinker = ElasticSinker::new(&json!({
+			"url": format!("http://{}/", addr),
+			"index": "event-{kind}",
+			"id_field": "id",
+			"op": "create"
+		}))?;
+		let errors = SinkErrors::default();
+		sinker.bind_errors(errors.clone());
+
+		let (s, r) = mpsc::channel(1);
+		let rows = json!([
+			{"id": 1, "kind": "ok"},
+			{"id": 2, "kind": "bad"},
+			{"id": 3, "kind": "dup"},
+			{"id": 4}
+		]);
+		s.send(CoreMsg::default().with_result(serde_json::from_value(rows)?)).await?;
+		drop(s);
+		sinker.sink(r).await?;
+
+		let bodies = bodies.lock().unwrap().clone();
+		let lines = bodies[0].lines().collect::<Vec<_>>();
+		assert_eq!(lines.len(), 6);
+		assert_eq!(lines[0], r#"{"create":{"_index":"event-ok","_id":"1"}}"#);
+		// bad item and the row without index
+		assert_eq!(errors.take(), 2);
+		server.abort();
+		Ok(())
+	}
+}
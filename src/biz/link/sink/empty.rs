@@ -23,6 +23,7 @@ impl Sinker for EmptySinker {
 		info!("start sink {}", serde_json::json!(self.val).to_string());
 		while let Some(body) = r.recv().await {
 			info!("receive data {:?}", body);
+			body.done();
 		}
 		info!("close sender");
 		Ok(())
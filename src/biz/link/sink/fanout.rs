@@ -0,0 +1,519 @@
+use std::sync::Mutex;
+
+use anyhow::Context;
+
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+
+use regex::Regex;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::info;
+
+use lepumk::ani::Row;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::SinkErrors;
+use super::get_sinker;
+use super::Sinker;
+use super::SinkerEnum;
+
+// dst config, single sink like {"name": "kafka", "val": {..}}
+// or a list of sink or {"sinks": [..], "on_error": "dlq", "dlq": {..}}
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DstConf {
+	Single(SinkConf),
+	List(Vec<SinkConf>),
+	Fanout(FanoutConf),
+}
+
+#[derive(Debug, Deserialize)]
+struct FanoutConf {
+	sinks: Vec<SinkConf>,
+	#[serde(default)]
+	on_error: OnSinkError,
+	#[serde(default)]
+	dlq: Option<SinkConf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SinkConf {
+	name: String,
+	#[serde(default)]
+	val: serde_json::Value,
+	// all must match, empty sink every row
+	#[serde(default)]
+	filter: Vec<RowFilter>,
+}
+
+// policy of a sink stop or rows failed in a sink
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnSinkError {
+	// stop the task, failed rows are delivered again by source
+	#[default]
+	Fail,
+	// drop the sink or the failed rows, the others keep going
+	Continue,
+	// rows of the failed sink go to dlq sink
+	Dlq,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RowFilter {
+	pub field: String,
+	#[serde(flatten)]
+	pub cond: Cond,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Cond {
+	Eq { value: serde_json::Value },
+	Ne { value: serde_json::Value },
+	In { values: Vec<serde_json::Value> },
+	// field present and not null
+	Exists,
+	// string value match
+	Regex { pattern: String },
+}
+
+enum Matcher {
+	Eq(serde_json::Value),
+	Ne(serde_json::Value),
+	In(Vec<serde_json::Value>),
+	Exists,
+	Regex(Regex),
+}
+
+struct Filter {
+	field: String,
+	matcher: Matcher,
+}
+
+impl Filter {
+	fn new(filter: &RowFilter) -> anyhow::Result<Self> {
+		let matcher = match &filter.cond {
+			Cond::Eq { value } => Matcher::Eq(value.clone()),
+			Cond::Ne { value } => Matcher::Ne(value.clone()),
+			Cond::In { values } => Matcher::In(values.clone()),
+			Cond::Exists => Matcher::Exists,
+			Cond::Regex { pattern } => Matcher::Regex(
+				Regex::new(pattern)
+					.map_err(|err| anyhow::anyhow!("filter pattern {} error {}", pattern, err))?,
+			),
+		};
+		Ok(Self { field: filter.field.clone(), matcher })
+	}
+
+	fn is_match(&self, row: &Row) -> bool {
+		let val = row.get(&self.field).unwrap_or(&serde_json::Value::Null);
+		match &self.matcher {
+			Matcher::Eq(value) => val == value,
+			Matcher::Ne(value) => val != value,
+			Matcher::In(values) => values.contains(val),
+			Matcher::Exists => !val.is_null(),
+			Matcher::Regex(re) => val.as_str().is_some_and(|s| re.is_match(s)),
+		}
+	}
+}
+
+struct Target {
+	name: String,
+	sink: SinkerEnum,
+	filter: Vec<Filter>,
+}
+
+impl Target {
+	fn new(conf: &SinkConf) -> anyhow::Result<Self> {
+		let sink = get_sinker(&conf.name, &conf.val)?;
+		let filter = conf.filter.iter().map(Filter::new).collect::<anyhow::Result<Vec<_>>>()?;
+		Ok(Self { name: conf.name.clone(), sink, filter })
+	}
+
+	// msg with matched rows, none if no row match
+	fn select(&self, msg: &CoreMsg) -> Option<CoreMsg> {
+		let rows = msg
+			.result
+			.iter()
+			.filter(|row| self.filter.iter().all(|f| f.is_match(row)))
+			.cloned()
+			.collect::<Vec<_>>();
+		if rows.is_empty() {
+			return None;
+		}
+		Some(CoreMsg { result: rows, ..msg.clone() })
+	}
+}
+
+// failed rows of sinks with the sink index
+type Failed = (usize, CoreMsg);
+
+// broadcast the task rows to every sink
+pub struct FanoutSinker {
+	targets: Vec<Target>,
+	on_error: OnSinkError,
+	dlq: Option<Target>,
+	// one sink without error policy, failed rows are left to source redelivery
+	direct: bool,
+	failed: (mpsc::UnboundedSender<Failed>, Mutex<Option<mpsc::UnboundedReceiver<Failed>>>),
+}
+
+impl FanoutSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<Self> {
+		let conf = from_val::<DstConf>(val)
+			.with_context(|| format!("build dst config {}", serde_json::json!(val)))?;
+		let (conf, policy) = match conf {
+			DstConf::Single(sink) => {
+				(FanoutConf { sinks: vec![sink], on_error: OnSinkError::Fail, dlq: None }, false)
+			}
+			DstConf::List(sinks) => {
+				(FanoutConf { sinks, on_error: OnSinkError::Fail, dlq: None }, false)
+			}
+			DstConf::Fanout(conf) => (conf, true),
+		};
+		if conf.sinks.is_empty() {
+			anyhow::bail!("dst config need at least one sink");
+		}
+		if (conf.on_error == OnSinkError::Dlq) != conf.dlq.is_some() {
+			anyhow::bail!("dst config dlq sink must set with on_error dlq only");
+		}
+
+		let targets = conf.sinks.iter().map(Target::new).collect::<anyhow::Result<Vec<_>>>()?;
+		let dlq = conf.dlq.as_ref().map(Target::new).transpose()?;
+		// without an error policy one sink without filter need no fan-out
+		let direct = !policy && targets.len() == 1 && targets[0].filter.is_empty();
+		let (s, r) = mpsc::unbounded_channel();
+		let mut sinker = Self {
+			targets,
+			on_error: conf.on_error,
+			dlq,
+			direct,
+			failed: (s, Mutex::new(Some(r))),
+		};
+		sinker.bind_errors(SinkErrors::default());
+		Ok(sinker)
+	}
+}
+
+impl FanoutSinker {
+	// failed rows of sinks and dlq count into errors, failed rows of sinks go to the policy
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		for (i, target) in self.targets.iter_mut().enumerate() {
+			let errors = match self.direct {
+				true => errors.clone(),
+				false => errors.clone().with_failed(i, self.failed.0.clone()),
+			};
+			target.sink.bind_errors(errors);
+		}
+		if let Some(dlq) = &mut self.dlq {
+			dlq.sink.bind_errors(errors);
+		}
+	}
+
+	// pipe names of sinks and dlq
+	pub fn pipes(&self) -> Vec<String> {
+		self.targets
+			.iter()
+			.chain(self.dlq.iter())
+			.filter_map(|target| match &target.sink {
+				SinkerEnum::PipeSinker(sink) => Some(sink.get_name().to_owned()),
+				_ => None,
+			})
+			.collect()
+	}
+}
+
+impl Sinker for FanoutSinker {
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		if self.direct {
+			return self.targets[0].sink.sink(r).await;
+		}
+		self.fanout(r).await
+	}
+}
+
+impl FanoutSinker {
+	// dispatch rows and drive every sink at the same time
+	async fn fanout(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let all = self.targets.iter().chain(self.dlq.iter()).collect::<Vec<_>>();
+		let mut senders = Vec::with_capacity(all.len());
+		let mut running = FuturesUnordered::new();
+		for (i, target) in all.iter().enumerate() {
+			let (s, r) = mpsc::channel(6);
+			senders.push(Some(s));
+			running.push(async move { (i, target.sink.sink(r).await) });
+		}
+		// dlq sender is the last
+		let mut dlq = if self.dlq.is_some() { senders.pop().flatten() } else { None };
+		let mut failed = self.failed.1.lock().unwrap().take().context("fan-out sink is running")?;
+
+		let dispatch = self.dispatch(r, senders, dlq.clone());
+		tokio::pin!(dispatch);
+		let mut dispatched = false;
+		let mut live = self.targets.len();
+		let mut drained = false;
+		loop {
+			// sinks finished, dlq finish after the last failed rows
+			if dispatched && live == 0 && !drained {
+				while let Ok((i, msg)) = failed.try_recv() {
+					self.failed(&self.targets[i], msg, dlq.as_ref()).await?;
+				}
+				dlq = None;
+				drained = true;
+			}
+			if dispatched && running.is_empty() {
+				break;
+			}
+			tokio::select! {
+				res = &mut dispatch, if !dispatched => {
+					res?;
+					info!("close fan-out sinks");
+					dispatched = true;
+				},
+				Some((i, msg)) = failed.recv() => {
+					self.failed(&self.targets[i], msg, dlq.as_ref()).await?;
+				},
+				Some((i, res)) = running.next(), if !running.is_empty() => {
+					if i == self.targets.len() {
+						res.context("dlq sink")?;
+						if !dispatched {
+							anyhow::bail!("dlq sink stopped");
+						}
+						continue;
+					}
+					live -= 1;
+					self.stopped(all[i], res)?;
+				},
+			}
+		}
+		Ok(())
+	}
+
+	// rows failed in a sink after its retries
+	async fn failed(
+		&self,
+		target: &Target,
+		msg: CoreMsg,
+		dlq: Option<&mpsc::Sender<CoreMsg>>,
+	) -> anyhow::Result<()> {
+		match (dlq, self.on_error) {
+			(_, OnSinkError::Fail) => {
+				anyhow::bail!("sink {} failed {} rows", target.name, msg.result.len())
+			}
+			(Some(dlq), _) => {
+				dlq.send(msg).await.map_err(|_| anyhow::anyhow!("dlq sink stopped"))?;
+			}
+			// continue policy drop the failed rows
+			(None, _) => {
+				error!("sink {} drop {} failed rows", target.name, msg.result.len());
+				msg.done();
+			}
+		}
+		Ok(())
+	}
+
+	// senders are dropped when receiver close, then sinks finish
+	async fn dispatch(
+		&self,
+		mut r: mpsc::Receiver<CoreMsg>,
+		mut senders: Vec<Option<mpsc::Sender<CoreMsg>>>,
+		dlq: Option<mpsc::Sender<CoreMsg>>,
+	) -> anyhow::Result<()> {
+		while let Some(msg) = r.recv().await {
+			for (i, target) in self.targets.iter().enumerate() {
+				let Some(selected) = target.select(&msg) else {
+					continue;
+				};
+				let selected = match &senders[i] {
+					Some(s) => match s.send(selected).await {
+						Ok(_) => continue,
+						// sink stopped, its result is handled by fanout
+						Err(err) => {
+							senders[i] = None;
+							err.0
+						}
+					},
+					None => selected,
+				};
+				match (&dlq, self.on_error) {
+					(_, OnSinkError::Fail) => anyhow::bail!("sink {} stopped", target.name),
+					(Some(dlq), _) => {
+						dlq.send(selected)
+							.await
+							.map_err(|_| anyhow::anyhow!("dlq sink stopped"))?;
+					}
+					// continue policy drop rows of the stopped sink
+					(None, _) => selected.done(),
+				}
+			}
+			// handed over to the copies of sinks
+			msg.done();
+			if senders.iter().all(Option::is_none) && dlq.is_none() {
+				anyhow::bail!("all sinks stopped");
+			}
+		}
+		Ok(())
+	}
+
+	// sink stop before the task, fail policy stop the task
+	fn stopped(&self, target: &Target, res: anyhow::Result<()>) -> anyhow::Result<()> {
+		match (res, self.on_error) {
+			(Ok(_), _) => {
+				info!("sink {} stopped", target.name);
+				Ok(())
+			}
+			(Err(err), OnSinkError::Fail) => Err(err.context(format!("sink {}", target.name))),
+			(Err(err), policy) => {
+				error!("sink {} error {:?}, policy {:?}", target.name, err, policy);
+				Ok(())
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use axum::extract::State;
+	use axum::http::StatusCode;
+	use axum::routing::post;
+	use axum::Router;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use lepumk::ani::Row;
+
+	use super::FanoutSinker;
+	use super::Filter;
+	use super::RowFilter;
+	use crate::biz::link::sink::Sinker;
+	use crate::core::Ack;
+	use crate::core::AckKind;
+	use crate::core::CoreMsg;
+
+	type Bodies = Arc<Mutex<Vec<String>>>;
+
+	async fn reject() -> StatusCode {
+		StatusCode::BAD_REQUEST
+	}
+
+	async fn dlq(State(bodies): State<Bodies>, body: String) -> StatusCode {
+		bodies.lock().unwrap().push(body);
+		StatusCode::OK
+	}
+
+	#[test]
+	fn test_filter() {
+		let filters: Vec<RowFilter> = serde_json::from_value(json!([
+			{"field": "level", "op": "in", "values": ["warn", "error"]},
+			{"field": "msg", "op": "regex", "pattern": "^timeout"},
+			{"field": "user", "op": "exists"},
+			{"field": "env", "op": "ne", "value": "test"}
+		]))
+		.unwrap();
+		let filters = filters.iter().map(|f| Filter::new(f).unwrap()).collect::<Vec<_>>();
+		let is_match = |row: serde_json::Value| {
+			let row: Row = serde_json::from_value(row).unwrap();
+			filters.iter().all(|f| f.is_match(&row))
+		};
+		assert!(is_match(json!({"level": "warn", "msg": "timeout 3s", "user": 1})));
+		assert!(!is_match(json!({"level": "info", "msg": "timeout 3s", "user": 1})));
+		assert!(!is_match(json!({"level": "warn", "msg": "timeout 3s", "user": null})));
+		assert!(!is_match(json!({"level": "warn", "msg": "timeout", "user": 1, "env": "test"})));
+	}
+
+	#[tokio::test]
+	async fn test_fanout() -> anyhow::Result<()> {
+		assert!(FanoutSinker::new(&json!([])).is_err());
+		assert!(
+			FanoutSinker::new(&json!({"sinks": [{"name": "empty"}], "on_error": "dlq"})).is_err()
+		);
+		assert!(FanoutSinker::new(&json!([{"name": "nope"}])).is_err());
+
+		let sinker = FanoutSinker::new(&json!({
+			"sinks": [
+				{"name": "empty", "filter": [{"field": "level", "op": "eq", "value": "error"}]},
+				{"name": "empty"}
+			],
+			"on_error": "dlq",
+			"dlq": {"name": "empty"}
+		}))?;
+		assert_eq!(sinker.targets.len(), 2);
+
+		let msg = CoreMsg::default().with_result(serde_json::from_value(json!([
+			{"level": "error"},
+			{"level": "info"}
+		]))?);
+		assert_eq!(sinker.targets[0].select(&msg).unwrap().result.len(), 1);
+		assert_eq!(sinker.targets[1].select(&msg).unwrap().result.len(), 2);
+
+		// every sink finish after the receiver close
+		let (s, r) = mpsc::channel(6);
+		s.send(msg).await?;
+		drop(s);
+		sinker.sink(r).await
+	}
+
+	#[tokio::test]
+	async fn test_fanout_failed() -> anyhow::Result<()> {
+		let bodies = Bodies::default();
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let url = format!("http://{}", listener.local_addr()?);
+		let app = Router::new()
+			.route("/reject", post(reject))
+			.route("/dlq", post(dlq))
+			.with_state(bodies.clone());
+		let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+		let sink = |on_error: &str| {
+			let http = |path: &str| json!({"name": "http", "val": {"url": format!("{}{}", url, path), "linger_ms": 1}});
+			let mut conf =
+				json!({"sinks": [http("/reject"), {"name": "empty"}], "on_error": on_error});
+			if on_error == "dlq" {
+				conf["dlq"] = http("/dlq");
+			}
+			FanoutSinker::new(&conf)
+		};
+		// rows failed in http sink after the retries
+		let run = |sinker: FanoutSinker| async move {
+			let (acks, mut settled) = mpsc::unbounded_channel();
+			let rows = serde_json::from_value(json!([{"a": 1}]))?;
+			let msg = CoreMsg::default()
+				.with_result(rows)
+				.with_ack(Some(Ack::new("1".to_owned(), acks.clone())));
+			let (s, r) = mpsc::channel(6);
+			s.send(msg).await?;
+			drop(s);
+			let res = sinker.sink(r).await;
+			anyhow::Ok((res, settled.try_recv()?.kind))
+		};
+
+		let (res, kind) = run(sink("dlq")?).await?;
+		assert!(res.is_ok());
+		assert_eq!(kind, AckKind::Done);
+		assert_eq!(*bodies.lock().unwrap(), ["[{\"a\":1}]"]);
+
+		let (res, kind) = run(sink("continue")?).await?;
+		assert!(res.is_ok());
+		assert_eq!(kind, AckKind::Done);
+
+		let (res, kind) = run(sink("fail")?).await?;
+		assert!(res.is_err());
+		assert_eq!(kind, AckKind::Retry);
+
+		server.abort();
+		Ok(())
+	}
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::instrument;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::run_batches;
+use super::batch::send_retry;
+use super::batch::Batch;
+use super::batch::BatchConfig;
+use super::batch::RetryConfig;
+use super::batch::SinkErrors;
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct HttpSinkArg {
+	url: String,
+	#[serde(default)]
+	headers: HashMap<String, String>,
+	#[serde(default)]
+	encoding: Encoding,
+	#[serde(flatten)]
+	batch: BatchConfig,
+	// retry on 5xx, 429 and timeout
+	#[serde(flatten)]
+	retry: RetryConfig,
+	// request timeout seconds
+	#[serde(default = "default_timeout")]
+	timeout: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Encoding {
+	// json array of rows
+	#[default]
+	Json,
+	// row per line
+	Ndjson,
+}
+
+fn default_timeout() -> u64 {
+	30
+}
+
+// post batches of rows, failed batch count into handle_err and retried by source
+pub struct HttpSinker {
+	arg: HttpSinkArg,
+	errors: SinkErrors,
+}
+
+impl HttpSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<HttpSinker> {
+		let arg: HttpSinkArg = from_val(val)?;
+		reqwest::Url::parse(&arg.url).with_context(|| format!("http sink url {}", arg.url))?;
+		Ok(Self { arg, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	fn client(&self) -> anyhow::Result<reqwest::Client> {
+		let mut headers = reqwest::header::HeaderMap::new();
+		for (k, v) in self.arg.headers.iter() {
+			headers.insert(
+				reqwest::header::HeaderName::from_bytes(k.as_bytes())?,
+				reqwest::header::HeaderValue::from_str(v)?,
+			);
+		}
+		let content_type = match self.arg.encoding {
+			Encoding::Json => "application/json",
+			Encoding::Ndjson => "application/x-ndjson",
+		};
+		headers
+			.entry(reqwest::header::CONTENT_TYPE)
+			.or_insert(reqwest::header::HeaderValue::from_static(content_type));
+		let client = reqwest::Client::builder()
+			.default_headers(headers)
+			.timeout(Duration::from_secs(self.arg.timeout))
+			.build()?;
+		Ok(client)
+	}
+
+	fn body(&self, batch: &Batch) -> String {
+		match self.arg.encoding {
+			Encoding::Json => format!("[{}]", batch.lines.join(",")),
+			Encoding::Ndjson => {
+				let mut body = batch.lines.join("\n");
+				body.push('\n');
+				body
+			}
+		}
+	}
+
+	async fn post(&self, client: &reqwest::Client, batch: Batch) {
+		let body = self.body(&batch);
+		let res = send_retry(&self.arg.url, &self.arg.retry, || {
+			client.post(&self.arg.url).body(body.clone())
+		})
+		.await;
+		match res {
+			Ok(_) => batch.done(),
+			Err(err) => {
+				error!("http sink {} rows {} error {:?}", self.arg.url, batch.len(), err);
+				self.errors.fail(batch.into_msg());
+			}
+		}
+	}
+}
+
+impl Sinker for HttpSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let client = self.client()?;
+		run_batches(r, &self.arg.batch, |batch| {
+			let client = &client;
+			async move {
+				self.post(client, batch).await;
+				Ok(())
+			}
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use axum::extract::State;
+	use axum::http::StatusCode;
+	use axum::routing::post;
+	use axum::Router;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::HttpSinker;
+	use crate::biz::link::sink::batch::SinkErrors;
+	use crate::biz::link::sink::Sinker;
+	use crate::core::CoreMsg;
+
+	type Bodies = Arc<Mutex<Vec<String>>>;
+
+	// fail the first request of every body, reject body with bad
+	async fn hook(State(bodies): State<Bodies>, body: String) -> StatusCode {
+		let mut bodies = bodies.lock().unwrap();
+		if body.contains("bad") {
+			return StatusCode::BAD_REQUEST;
+		}
+		let seen = bodies.contains(&body);
+		bodies.push(body);
+		if seen {
+			StatusCode::OK
+		} else {
+			StatusCode::SERVICE_UNAVAILABLE
+		}
+	}
+
+	#[tokio::test]
+	async fn test_http_sink() -> anyhow::Result<()> {
+		let bodies = Bodies::default();
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let app = Router::new().route("/hook", post(hook)).with_state(bodies.clone());
+		let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+		let mut sinker = HttpSinker::new(&json!({
+			"url": format!("http://{}/hook", addr),
+			"encoding": "ndjson",
+			"batch_rows": 2,
+			"retry_backoff_ms": 1
+		}))?;
+		let errors = SinkErrors::default();
+		sinker.bind_errors(errors.clone());
+
+		let (s, r) = mpsc::channel(6);
+		let rows = json!([{"a": 1}, {"a": 2}, {"a": "bad"}]);
+		s.send(CoreMsg::default().with_result(serde_json::from_value(rows)?)).await?;
+		drop(s);
+		sinker.sink(r).await?;
+
+		let bodies = bodies.lock().unwrap().clone();
+		assert_eq!(bodies, vec!["{\"a\":1}\n{\"a\":2}\n"; 2]);
+		assert_eq!(errors.take(), 1);
+		server.abort();
+		Ok(())
+	}
+}
@@ -13,6 +13,7 @@ use serde_json::json;
 use query_map::QueryMap;
 
 use tracing::debug;
+use tracing::error;
 use tracing::instrument;
 
 use crate::core::CoreMsg;
@@ -73,6 +74,7 @@ impl KafkaSinker {
 	pub async fn produce(&self, mut r: tokio::sync::mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
 		let producer = self.producer_client().await?;
 		while let Some(msg) = r.recv().await {
+			let mut sent = true;
 			for data in msg.result.iter() {
 				let payload = json!(data).to_string();
 				debug!("receive message {payload}");
@@ -84,7 +86,14 @@ impl KafkaSinker {
 					.payload(&payload)
 					.headers(headers);
 				// send data
-				let _ = producer.send(record, Duration::from_secs(0)).await;
+				if let Err((err, _)) = producer.send(record, Duration::from_secs(0)).await {
+					error!("kafka sink {} error {:?}", self.arg.get_topic(), err);
+					sent = false;
+				}
+			}
+			// source messages of unsent rows are delivered again
+			if sent {
+				msg.done();
 			}
 		}
 		Ok(())
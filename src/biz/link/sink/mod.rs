@@ -7,20 +7,75 @@ use tracing::info;
 
 use crate::core::CoreMsg;
 
+pub mod amqp;
+pub mod batch;
+pub mod clickhouse;
+pub mod elastic;
 pub mod empty;
+pub mod fanout;
+pub mod http;
 pub mod kafka;
+pub mod mysql;
+pub mod nats;
+pub mod pipe;
+pub mod redis;
+pub mod template;
 
+use amqp::*;
+use batch::SinkErrors;
+use clickhouse::*;
+use elastic::*;
 use empty::*;
+use http::*;
 use kafka::*;
+use mysql::*;
+use nats::*;
+use pipe::*;
+use redis::*;
 
 lazy_static! {
-	pub static ref SinkNames: Vec<&'static str> = vec!["kafka", "empty"];
+	pub static ref SinkNames: Vec<&'static str> = vec![
+		"kafka",
+		"empty",
+		"pipe",
+		"http",
+		"mysql",
+		"clickhouse",
+		"elastic",
+		"redis",
+		"nats",
+		"amqp"
+	];
 }
 
 #[enum_dispatch]
 pub enum SinkerEnum {
 	EmptySinker,
 	KafkaSinker,
+	PipeSinker,
+	HttpSinker,
+	MySqlSinker,
+	ClickHouseSinker,
+	ElasticSinker,
+	RedisSinker,
+	NatsSinker,
+	AmqpSinker,
+}
+
+impl SinkerEnum {
+	// sinkers drop failed rows count them into errors
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		match self {
+			SinkerEnum::HttpSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::MySqlSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::ClickHouseSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::ElasticSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::RedisSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::NatsSinker(sink) => sink.bind_errors(errors),
+			SinkerEnum::AmqpSinker(sink) => sink.bind_errors(errors),
+			_ => {}
+		}
+	}
 }
 
 #[allow(async_fn_in_trait)]
@@ -35,6 +90,14 @@ pub fn get_sinker(name: &str, val: &serde_json::Value) -> anyhow::Result<SinkerE
 	match name.to_lowercase().as_str() {
 		"kafka" => Ok(KafkaSinker::new(val)?.into()),
 		"empty" => Ok(EmptySinker::new(val)?.into()),
+		"pipe" => Ok(PipeSinker::new(val)?.into()),
+		"http" => Ok(HttpSinker::new(val)?.into()),
+		"mysql" => Ok(MySqlSinker::new(val)?.into()),
+		"clickhouse" => Ok(ClickHouseSinker::new(val)?.into()),
+		"elastic" | "opensearch" => Ok(ElasticSinker::new(val)?.into()),
+		"redis" => Ok(RedisSinker::new(val)?.into()),
+		"nats" => Ok(NatsSinker::new(val)?.into()),
+		"amqp" => Ok(AmqpSinker::new(val)?.into()),
 		other => anyhow::bail!("unknown data sinker {}", other),
 	}
 }
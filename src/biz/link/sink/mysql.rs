@@ -0,0 +1,461 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+
+use indexmap::IndexMap;
+
+use serde::Deserialize;
+
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::MySql;
+use sqlx::MySqlPool;
+use sqlx::QueryBuilder;
+use sqlx::Row as _;
+
+use tokio::sync::mpsc;
+use tokio::sync::Mutex;
+
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use lepumk::ani::Row;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::run_batches;
+use super::batch::Batch;
+use super::batch::BatchConfig;
+use super::batch::RetryConfig;
+use super::batch::SinkErrors;
+use super::Sinker;
+
+// placeholders limit of one mysql statement
+const MAX_PARAMS: usize = 65535;
+
+#[derive(Debug, Deserialize)]
+struct MySqlSinkArg {
+	// target database, not the metadata db
+	dsn: String,
+	// table or db.table
+	table: String,
+	// column to flattened row key, empty use row keys as columns
+	#[serde(default)]
+	columns: IndexMap<String, String>,
+	#[serde(default)]
+	mode: InsertMode,
+	// create table from the first batch if not exists, add columns for new row keys
+	#[serde(default)]
+	auto_create: bool,
+	// primary key columns of auto created table, upsert update other columns
+	#[serde(default)]
+	keys: Vec<String>,
+	#[serde(flatten)]
+	batch: BatchConfig,
+	#[serde(default = "default_max_conn")]
+	max_conn: u32,
+	// retry on connection error, deadlock and lock wait timeout
+	#[serde(flatten)]
+	retry: RetryConfig,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InsertMode {
+	#[default]
+	Insert,
+	// insert ... on duplicate key update
+	Upsert,
+	// insert ignore
+	Ignore,
+}
+
+fn default_max_conn() -> u32 {
+	4
+}
+
+// quote identifier, db.table quote both parts
+fn quote(ident: &str) -> String {
+	ident
+		.split('.')
+		.map(|part| format!("`{}`", part.replace('`', "``")))
+		.collect::<Vec<_>>()
+		.join(".")
+}
+
+fn quote_column(column: &str) -> String {
+	format!("`{}`", column.replace('`', "``"))
+}
+
+// mysql type of a json value, none for null
+fn column_type(val: &serde_json::Value, key: bool) -> Option<&'static str> {
+	match val {
+		serde_json::Value::Null => None,
+		serde_json::Value::Bool(_) => Some("BOOLEAN"),
+		serde_json::Value::Number(n) if n.is_i64() => Some("BIGINT"),
+		serde_json::Value::Number(n) if n.is_u64() => Some("BIGINT UNSIGNED"),
+		serde_json::Value::Number(_) => Some("DOUBLE"),
+		// text can not be primary key
+		serde_json::Value::String(_) if key => Some("VARCHAR(255)"),
+		serde_json::Value::String(_) => Some("TEXT"),
+		serde_json::Value::Array(_) | serde_json::Value::Object(_) => Some("JSON"),
+	}
+}
+
+// transient error worth a retry
+fn is_transient(err: &sqlx::Error) -> bool {
+	match err {
+		sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+		sqlx::Error::Database(err) => err
+			.try_downcast_ref::<MySqlDatabaseError>()
+			// lock wait timeout, deadlock
+			.is_some_and(|err| matches!(err.number(), 1205 | 1213)),
+		_ => false,
+	}
+}
+
+// batch insert rows into a mysql table, failed batch count into handle_err
+pub struct MySqlSinker {
+	arg: MySqlSinkArg,
+	errors: SinkErrors,
+}
+
+impl MySqlSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<MySqlSinker> {
+		let arg: MySqlSinkArg = from_val(val)?;
+		if arg.table.is_empty() {
+			anyhow::bail!("mysql sink table is empty");
+		}
+		if arg.max_conn == 0 {
+			anyhow::bail!("mysql sink max_conn must larger than 0");
+		}
+		if let Some(key) =
+			arg.keys.iter().find(|key| !arg.columns.is_empty() && !arg.columns.contains_key(*key))
+		{
+			anyhow::bail!("mysql sink key {} is not in columns", key);
+		}
+		Ok(Self { arg, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	// column and row key, configured or all keys of the batch
+	fn columns(&self, rows: &[Row]) -> Vec<(String, String)> {
+		if !self.arg.columns.is_empty() {
+			return self.arg.columns.iter().map(|(c, k)| (c.clone(), k.clone())).collect();
+		}
+		let mut seen = HashSet::new();
+		rows.iter()
+			.flat_map(|row| row.keys())
+			.filter(|key| seen.insert(key.as_str()))
+			.map(|key| (key.clone(), key.clone()))
+			.collect()
+	}
+
+	// type inferred from the first non null value of the column
+	fn column_def(&self, column: &str, key: &str, rows: &[Row]) -> String {
+		let is_key = self.arg.keys.iter().any(|k| k == column);
+		let ty = rows
+			.iter()
+			.find_map(|row| row.get(key).and_then(|val| column_type(val, is_key)))
+			.unwrap_or(if is_key { "VARCHAR(255)" } else { "TEXT" });
+		let null = if is_key { " NOT NULL" } else { "" };
+		format!("{} {}{}", quote_column(column), ty, null)
+	}
+
+	fn create_sql(&self, columns: &[(String, String)], rows: &[Row]) -> String {
+		let mut defs = columns
+			.iter()
+			.map(|(column, key)| self.column_def(column, key, rows))
+			.collect::<Vec<_>>();
+		if !self.arg.keys.is_empty() {
+			let keys = self.arg.keys.iter().map(|key| quote_column(key)).collect::<Vec<_>>();
+			defs.push(format!("PRIMARY KEY ({})", keys.join(", ")));
+		}
+		format!("CREATE TABLE IF NOT EXISTS {} ({})", quote(&self.arg.table), defs.join(", "))
+	}
+
+	// add columns not in the table, none if all exist
+	fn alter_sql(
+		&self,
+		columns: &[(String, String)],
+		rows: &[Row],
+		exists: &HashSet<String>,
+	) -> Option<String> {
+		let adds = columns
+			.iter()
+			.filter(|(column, _)| !exists.contains(&column.to_lowercase()))
+			.map(|(column, key)| format!("ADD COLUMN {}", self.column_def(column, key, rows)))
+			.collect::<Vec<_>>();
+		if adds.is_empty() {
+			return None;
+		}
+		Some(format!("ALTER TABLE {} {}", quote(&self.arg.table), adds.join(", ")))
+	}
+
+	// lowercase column names, mysql column names are case insensitive
+	async fn table_columns(&self, conn: &MySqlPool) -> anyhow::Result<HashSet<String>> {
+		let sql = format!("SHOW COLUMNS FROM {}", quote(&self.arg.table));
+		let rows = sqlx::query(&sql).fetch_all(conn).await?;
+		rows.iter()
+			.map(|row| {
+				let field: Vec<u8> = row.try_get("Field")?;
+				Ok(String::from_utf8_lossy(&field).to_lowercase())
+			})
+			.collect()
+	}
+
+	// create table on the first batch, add columns of new row keys on later batches
+	async fn ensure_table(
+		&self,
+		conn: &MySqlPool,
+		table: &Mutex<Option<HashSet<String>>>,
+		columns: &[(String, String)],
+		rows: &[Row],
+	) -> anyhow::Result<()> {
+		let mut table = table.lock().await;
+		let exists = match &mut *table {
+			Some(exists) => exists,
+			None => {
+				let sql = self.create_sql(columns, rows);
+				info!("mysql sink create table {}", sql);
+				sqlx::query(&sql)
+					.execute(conn)
+					.await
+					.with_context(|| format!("create table {}", sql))?;
+				table.insert(self.table_columns(conn).await?)
+			}
+		};
+		let Some(sql) = self.alter_sql(columns, rows, exists) else {
+			return Ok(());
+		};
+		info!("mysql sink alter table {}", sql);
+		match sqlx::query(&sql).execute(conn).await {
+			Ok(_) => {}
+			// added by another writer, reload the columns
+			Err(sqlx::Error::Database(err))
+				if err
+					.try_downcast_ref::<MySqlDatabaseError>()
+					.is_some_and(|e| e.number() == 1060) =>
+			{
+				warn!("mysql sink alter table {} error {:?}", self.arg.table, err);
+				*exists = self.table_columns(conn).await?;
+				return Ok(());
+			}
+			Err(err) => return Err(err).with_context(|| format!("alter table {}", sql)),
+		}
+		exists.extend(columns.iter().map(|(column, _)| column.to_lowercase()));
+		Ok(())
+	}
+
+	fn insert_query<'a>(
+		&self,
+		columns: &[(String, String)],
+		rows: &'a [Row],
+	) -> QueryBuilder<'a, MySql> {
+		let verb = match self.arg.mode {
+			InsertMode::Ignore => "INSERT IGNORE INTO",
+			_ => "INSERT INTO",
+		};
+		let names = columns.iter().map(|(column, _)| quote_column(column)).collect::<Vec<_>>();
+		let mut query = QueryBuilder::new(format!(
+			"{} {} ({}) ",
+			verb,
+			quote(&self.arg.table),
+			names.join(", ")
+		));
+		query.push_values(rows, |mut b, row| {
+			for (_, key) in columns {
+				match row.get(key).unwrap_or(&serde_json::Value::Null) {
+					serde_json::Value::Null => b.push_bind(None::<String>),
+					serde_json::Value::Bool(v) => b.push_bind(*v),
+					serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+						(Some(v), _) => b.push_bind(v),
+						(None, Some(v)) => b.push_bind(v),
+						_ => b.push_bind(n.as_f64()),
+					},
+					serde_json::Value::String(s) => b.push_bind(s.as_str()),
+					other => b.push_bind(other.to_string()),
+				};
+			}
+		});
+		if self.arg.mode == InsertMode::Upsert {
+			let updates = columns
+				.iter()
+				.filter(|(column, _)| !self.arg.keys.contains(column))
+				.map(|(column, _)| format!("{0} = VALUES({0})", quote_column(column)))
+				.collect::<Vec<_>>();
+			if !updates.is_empty() {
+				query.push(format!(" ON DUPLICATE KEY UPDATE {}", updates.join(", ")));
+			}
+		}
+		query
+	}
+
+	// whole batch in one transaction, split by placeholders limit
+	async fn insert(
+		&self,
+		conn: &MySqlPool,
+		columns: &[(String, String)],
+		rows: &[Row],
+	) -> Result<(), sqlx::Error> {
+		let chunk = (MAX_PARAMS / columns.len().max(1)).max(1);
+		let mut tx = conn.begin().await?;
+		for rows in rows.chunks(chunk) {
+			self.insert_query(columns, rows).build().execute(&mut *tx).await?;
+		}
+		tx.commit().await
+	}
+
+	async fn write(
+		&self,
+		conn: &MySqlPool,
+		table: &Mutex<Option<HashSet<String>>>,
+		batch: Batch,
+	) -> anyhow::Result<()> {
+		let columns = self.columns(&batch.rows);
+		if columns.is_empty() {
+			batch.done();
+			return Ok(());
+		}
+		if self.arg.auto_create {
+			self.ensure_table(conn, table, &columns, &batch.rows).await?;
+		}
+
+		let mut attempt = 0;
+		loop {
+			let res = self.insert(conn, &columns, &batch.rows).await;
+			let err = match res {
+				Ok(_) => {
+					batch.done();
+					return Ok(());
+				}
+				Err(err) => err,
+			};
+			if !is_transient(&err) || attempt >= self.arg.retry.max_retries {
+				error!("mysql sink {} rows {} error {:?}", self.arg.table, batch.len(), err);
+				self.errors.fail(batch.into_msg());
+				return Ok(());
+			}
+			let wait = self.arg.retry.backoff(attempt);
+			warn!("mysql sink {} retry {} after {:?} {:?}", self.arg.table, attempt + 1, wait, err);
+			tokio::time::sleep(wait).await;
+			attempt += 1;
+		}
+	}
+}
+
+impl Sinker for MySqlSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let conn = MySqlPoolOptions::new()
+			.max_connections(self.arg.max_conn)
+			.connect(&self.arg.dsn)
+			.await
+			.with_context(|| format!("connect mysql sink {}", self.arg.table))?;
+		// columns of the auto created table
+		let table = Mutex::new(None);
+		let res = run_batches(r, &self.arg.batch, |batch| {
+			let conn = &conn;
+			let table = &table;
+			async move { self.write(conn, table, batch).await }
+		})
+		.await;
+		conn.close().await;
+		res
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashSet;
+
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::MySqlSinker;
+
+	fn rows(rows: serde_json::Value) -> Vec<Row> {
+		serde_json::from_value(rows).unwrap()
+	}
+
+	#[test]
+	fn test_mysql_sql() -> anyhow::Result<()> {
+		let sinker = MySqlSinker::new(&json!({
+			"dsn": "mysql://root@127.0.0.1/test",
+			"table": "test.user_event",
+			"mode": "upsert",
+			"keys": ["id"]
+		}))?;
+		let rows = rows(json!([{"id": 1, "name": null}, {"id": 2, "name": "b", "tags": [1]}]));
+		let columns = sinker.columns(&rows);
+		assert_eq!(
+			columns.iter().map(|(c, _)| c.as_str()).collect::<Vec<_>>(),
+			["id", "name", "tags"]
+		);
+
+		assert_eq!(
+			sinker.create_sql(&columns, &rows),
+			"CREATE TABLE IF NOT EXISTS `test`.`user_event` (`id` BIGINT NOT NULL, \
+			 `name` TEXT, `tags` JSON, PRIMARY KEY (`id`))"
+		);
+		assert_eq!(
+			sinker.insert_query(&columns, &rows).sql(),
+			"INSERT INTO `test`.`user_event` (`id`, `name`, `tags`) VALUES (?, ?, ?), (?, ?, ?) \
+			 ON DUPLICATE KEY UPDATE `name` = VALUES(`name`), `tags` = VALUES(`tags`)"
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_mysql_alter() -> anyhow::Result<()> {
+		let sinker = MySqlSinker::new(&json!({
+			"dsn": "mysql://root@127.0.0.1/test",
+			"table": "test.user_event",
+			"auto_create": true,
+			"keys": ["id"]
+		}))?;
+		let exists = HashSet::from(["id".to_owned(), "name".to_owned()]);
+		let first = rows(json!([{"id": 1, "Name": "a"}]));
+		assert_eq!(sinker.alter_sql(&sinker.columns(&first), &first, &exists), None);
+
+		// key first seen in a later batch
+		let later = rows(json!([{"id": 2, "score": null}, {"id": 3, "score": 1.5, "ok": true}]));
+		assert_eq!(
+			sinker.alter_sql(&sinker.columns(&later), &later, &exists).unwrap(),
+			"ALTER TABLE `test`.`user_event` ADD COLUMN `score` DOUBLE, ADD COLUMN `ok` BOOLEAN"
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_mysql_columns() -> anyhow::Result<()> {
+		let sinker = MySqlSinker::new(&json!({
+			"dsn": "mysql://root@127.0.0.1/test",
+			"table": "event",
+			"mode": "ignore",
+			"columns": {"user_id": "user.id", "at": "ts"}
+		}))?;
+		let rows = rows(json!([{"user.id": 1, "ts": 2, "other": 3}]));
+		let columns = sinker.columns(&rows);
+		assert_eq!(
+			sinker.insert_query(&columns, &rows).sql(),
+			"INSERT IGNORE INTO `event` (`user_id`, `at`) VALUES (?, ?)"
+		);
+
+		assert!(MySqlSinker::new(&json!({
+			"dsn": "mysql://root@127.0.0.1/test",
+			"table": "event",
+			"columns": {"user_id": "user.id"},
+			"keys": ["id"]
+		}))
+		.is_err());
+		Ok(())
+	}
+}
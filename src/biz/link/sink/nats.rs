@@ -0,0 +1,123 @@
+use anyhow::Context;
+
+use async_nats::jetstream;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::biz::link::source::nats::NatsConnArg;
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::SinkErrors;
+use super::template::RowTemplate;
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct NatsSinkArg {
+	#[serde(flatten)]
+	conn: NatsConnArg,
+	// subject of row like events.{type}.{region}
+	subject: String,
+	// publish into jetstream and wait the ack of stream
+	#[serde(default)]
+	jetstream: bool,
+}
+
+// publish rows to subjects rendered from row fields, failed rows count into handle_err
+pub struct NatsSinker {
+	arg: NatsSinkArg,
+	subject: RowTemplate,
+	errors: SinkErrors,
+}
+
+impl NatsSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<NatsSinker> {
+		let arg: NatsSinkArg = from_val(val)?;
+		let subject = RowTemplate::new(&arg.subject).context("nats sink subject")?;
+		Ok(Self { arg, subject, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	async fn publish(&self, client: &async_nats::Client, mut msg: CoreMsg) -> anyhow::Result<()> {
+		let context = self.arg.jetstream.then(|| jetstream::new(client.clone()));
+		let mut pending = vec![];
+		let mut failed = vec![];
+		for (i, row) in msg.result.iter().enumerate() {
+			let Some(subject) = self.subject.render(row) else {
+				warn!("nats sink row without subject field");
+				failed.push(i);
+				continue;
+			};
+			let payload = json!(row).to_string();
+			match &context {
+				Some(context) => match context.publish(subject, payload.into()).await {
+					Ok(ack) => pending.push((i, ack)),
+					Err(err) => {
+						error!("nats sink publish error {:?}", err);
+						failed.push(i);
+					}
+				},
+				None => client.publish(subject, payload.into()).await?,
+			}
+		}
+		// wait acks of the whole msg
+		for (i, ack) in pending {
+			if let Err(err) = ack.await {
+				error!("nats sink jetstream ack error {:?}", err);
+				failed.push(i);
+			}
+		}
+		if context.is_none() {
+			client.flush().await?;
+		}
+		if failed.is_empty() {
+			msg.done();
+		} else {
+			msg.result = failed.iter().map(|i| std::mem::take(&mut msg.result[*i])).collect();
+			self.errors.fail(msg);
+		}
+		Ok(())
+	}
+}
+
+impl Sinker for NatsSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, mut r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let client = self.arg.conn.connect().await?;
+		while let Some(msg) = r.recv().await {
+			self.publish(&client, msg).await?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use super::NatsSinker;
+
+	#[test]
+	fn test_nats_sink_conf() -> anyhow::Result<()> {
+		let sinker = NatsSinker::new(&json!({
+			"url": "nats://127.0.0.1:4222",
+			"subject": "events.{type}",
+			"jetstream": true
+		}))?;
+		let row = serde_json::from_value(json!({"type": "click"}))?;
+		assert_eq!(sinker.subject.render(&row).as_deref(), Some("events.click"));
+
+		assert!(NatsSinker::new(&json!({"url": "nats://127.0.0.1", "subject": ""})).is_err());
+		Ok(())
+	}
+}
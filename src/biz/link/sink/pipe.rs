@@ -0,0 +1,97 @@
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::info;
+use tracing::instrument;
+
+use crate::biz::link::pipe::pipe;
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct PipeSinkArg {
+	// pipe name, same as the downstream pipe source
+	name: String,
+}
+
+// send rows to downstream task in the same process
+pub struct PipeSinker {
+	arg: PipeSinkArg,
+}
+
+impl PipeSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<PipeSinker> {
+		let arg = from_val(val)?;
+		Ok(Self { arg })
+	}
+
+	pub fn get_name(&self) -> &str {
+		&self.arg.name
+	}
+}
+
+impl Sinker for PipeSinker {
+	// one message per row, downstream parse the row like a kafka message,
+	// source messages are acked after the downstream task sinks the rows
+	#[instrument(skip(self, r))]
+	async fn sink(&self, mut r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let pipe = pipe(self.get_name());
+		info!("start pipe sink {}", self.get_name());
+		while let Some(msg) = r.recv().await {
+			for row in msg.result.iter() {
+				let raw_msg = serde_json::json!(row).to_string();
+				let row_msg = CoreMsg::default()
+					.with_raw_msg(raw_msg)
+					.with_timestamp(msg.timestamp)
+					.with_acks(msg.acks.clone());
+				pipe.sender().send(row_msg).await?;
+			}
+			msg.done();
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::PipeSinker;
+	use crate::biz::link::sink::Sinker;
+	use crate::biz::link::source::pipe::PipeSource;
+	use crate::biz::link::source::Source;
+	use crate::core::CoreMsg;
+
+	#[tokio::test]
+	async fn test_pipe() -> anyhow::Result<()> {
+		let sinker = PipeSinker::new(&json!({"name": "pipe_test_sink"}))?;
+		let source = PipeSource::new(&json!({"name": "pipe_test_sink"}))?;
+
+		let (s, r) = mpsc::channel(6);
+		let msg = CoreMsg::default()
+			.with_result(serde_json::from_value(json!([{"a": 1}, {"a": 2}]))?)
+			.with_timestamp(Some(1));
+		s.send(msg).await?;
+		drop(s);
+		sinker.sink(r).await?;
+
+		let (s, mut r) = mpsc::channel(6);
+		let task = tokio::spawn(async move { source.source(s).await });
+		let first = r.recv().await.unwrap();
+		assert_eq!(first.get_raw_msg(), r#"{"a":1}"#);
+		assert_eq!(first.timestamp, Some(1));
+		assert_eq!(r.recv().await.unwrap().get_raw_msg(), r#"{"a":2}"#);
+
+		// one source a pipe
+		let other = PipeSource::new(&json!({"name": "pipe_test_sink"}))?;
+		let (s2, _r2) = mpsc::channel(6);
+		assert!(other.source(s2).await.is_err());
+		task.abort();
+		Ok(())
+	}
+}
@@ -0,0 +1,225 @@
+use anyhow::Context;
+
+use redis::aio::ConnectionManager;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::instrument;
+use tracing::warn;
+
+use lepumk::ani::Row;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::batch::run_batches;
+use super::batch::Batch;
+use super::batch::BatchConfig;
+use super::batch::RetryConfig;
+use super::batch::SinkErrors;
+use super::Sinker;
+
+#[derive(Debug, Deserialize)]
+struct RedisSinkArg {
+	// redis://127.0.0.1:6379/0
+	url: String,
+	#[serde(flatten)]
+	mode: RedisMode,
+	#[serde(flatten)]
+	batch: BatchConfig,
+	// retry on connection error
+	#[serde(flatten)]
+	retry: RetryConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum RedisMode {
+	// XADD row into stream
+	Xadd {
+		stream: String,
+		// approximate max length of the stream
+		#[serde(default)]
+		maxlen: Option<usize>,
+		// one field holds the row json, empty add row keys as fields
+		#[serde(default)]
+		field: Option<String>,
+	},
+	// SET row json at prefix + key field
+	Set {
+		key_field: String,
+		#[serde(default)]
+		prefix: String,
+		// expire seconds
+		#[serde(default)]
+		ttl: Option<u64>,
+	},
+	// HSET row keys at prefix + key field
+	Hset {
+		key_field: String,
+		#[serde(default)]
+		prefix: String,
+		#[serde(default)]
+		ttl: Option<u64>,
+	},
+}
+
+// field value, string without quote
+fn to_field(val: &serde_json::Value) -> String {
+	match val {
+		serde_json::Value::String(s) => s.clone(),
+		other => other.to_string(),
+	}
+}
+
+fn fields(row: &Row) -> Vec<(String, String)> {
+	row.iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k.clone(), to_field(v))).collect()
+}
+
+// write rows by XADD, SET or HSET, failed rows count into handle_err and retried by source
+pub struct RedisSinker {
+	arg: RedisSinkArg,
+	errors: SinkErrors,
+}
+
+impl RedisSinker {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<RedisSinker> {
+		let arg: RedisSinkArg = from_val(val)?;
+		redis::parse_redis_url(&arg.url)
+			.ok_or_else(|| anyhow::anyhow!("redis sink url {} error", arg.url))?;
+		Ok(Self { arg, errors: SinkErrors::default() })
+	}
+
+	pub fn bind_errors(&mut self, errors: SinkErrors) {
+		self.errors = errors;
+	}
+
+	// commands of the batch in one pipeline, return index of rows without key
+	fn pipeline(&self, batch: &Batch) -> (redis::Pipeline, Vec<usize>) {
+		let mut pipe = redis::pipe();
+		let mut skipped = vec![];
+		for (i, (row, line)) in batch.rows.iter().zip(batch.lines.iter()).enumerate() {
+			match &self.arg.mode {
+				RedisMode::Xadd { stream, maxlen, field } => {
+					let mut cmd = redis::cmd("XADD");
+					cmd.arg(stream);
+					if let Some(maxlen) = maxlen {
+						cmd.arg("MAXLEN").arg("~").arg(maxlen);
+					}
+					cmd.arg("*");
+					match field {
+						Some(field) => {
+							cmd.arg(field).arg(line);
+						}
+						None => {
+							let fields = fields(row);
+							if fields.is_empty() {
+								skipped.push(i);
+								continue;
+							}
+							cmd.arg(fields);
+						}
+					}
+					pipe.add_command(cmd).ignore();
+				}
+				RedisMode::Set { key_field, prefix, ttl } => {
+					let Some(key) = row.get(key_field).filter(|v| !v.is_null()) else {
+						skipped.push(i);
+						continue;
+					};
+					let key = format!("{}{}", prefix, to_field(key));
+					match ttl {
+						Some(ttl) => pipe.set_ex(key, line, *ttl).ignore(),
+						None => pipe.set(key, line).ignore(),
+					};
+				}
+				RedisMode::Hset { key_field, prefix, ttl } => {
+					let Some(key) = row.get(key_field).filter(|v| !v.is_null()) else {
+						skipped.push(i);
+						continue;
+					};
+					let key = format!("{}{}", prefix, to_field(key));
+					pipe.hset_multiple(&key, &fields(row)).ignore();
+					if let Some(ttl) = ttl {
+						pipe.expire(&key, *ttl as i64).ignore();
+					}
+				}
+			}
+		}
+		(pipe, skipped)
+	}
+
+	async fn write(&self, conn: &ConnectionManager, batch: Batch) {
+		let (pipe, skipped) = self.pipeline(&batch);
+		let mut attempt = 0;
+		loop {
+			let res: redis::RedisResult<()> = pipe.query_async(&mut conn.clone()).await;
+			let err = match res {
+				Ok(_) if skipped.is_empty() => return batch.done(),
+				Ok(_) => {
+					warn!("redis sink {} rows without key or fields", skipped.len());
+					let mut msg = batch.into_msg();
+					msg.result =
+						skipped.iter().map(|i| std::mem::take(&mut msg.result[*i])).collect();
+					return self.errors.fail(msg);
+				}
+				Err(err) => err,
+			};
+			let transient = err.is_io_error() || err.is_connection_dropped() || err.is_timeout();
+			if !transient || attempt >= self.arg.retry.max_retries {
+				error!("redis sink rows {} error {:?}", batch.len(), err);
+				return self.errors.fail(batch.into_msg());
+			}
+			let wait = self.arg.retry.backoff(attempt);
+			warn!("redis sink retry {} after {:?} {:?}", attempt + 1, wait, err);
+			tokio::time::sleep(wait).await;
+			attempt += 1;
+		}
+	}
+}
+
+impl Sinker for RedisSinker {
+	#[instrument(skip(self, r))]
+	async fn sink(&self, r: mpsc::Receiver<CoreMsg>) -> anyhow::Result<()> {
+		let client = redis::Client::open(self.arg.url.as_str())?;
+		let conn = ConnectionManager::new(client).await.context("connect redis sink")?;
+		run_batches(r, &self.arg.batch, |batch| {
+			let conn = &conn;
+			async move {
+				self.write(conn, batch).await;
+				Ok(())
+			}
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use super::RedisSinker;
+
+	#[test]
+	fn test_redis_sink_conf() -> anyhow::Result<()> {
+		let sinker = RedisSinker::new(&json!({
+			"url": "redis://127.0.0.1:6379/0",
+			"mode": "set",
+			"key_field": "id",
+			"prefix": "user:",
+			"ttl": 60,
+			"batch_rows": 10
+		}))?;
+		assert_eq!(sinker.arg.batch.batch_rows, 10);
+
+		assert!(RedisSinker::new(&json!({"url": "redis://127.0.0.1", "mode": "xadd"})).is_err());
+		assert!(RedisSinker::new(
+			&json!({"url": "http://127.0.0.1", "mode": "set", "key_field": "id"})
+		)
+		.is_err());
+		Ok(())
+	}
+}
@@ -0,0 +1,95 @@
+use chrono::format::Item;
+use chrono::format::StrftimeItems;
+
+use lazy_static::lazy_static;
+
+use regex::Regex;
+
+use lepumk::ani::schema::to_timestamp;
+use lepumk::ani::Row;
+
+lazy_static! {
+	// {field} or {field:%Y.%m.%d}
+	static ref PLACEHOLDER: Regex = Regex::new(r"\{([^{}:]+)(?::([^{}]+))?\}").unwrap();
+}
+
+#[derive(Debug)]
+enum Part {
+	Text(String),
+	Field(String),
+	// utc date of a time field
+	Date(String, String),
+}
+
+// name rendered from row fields like logs-{service}-{ts:%Y.%m.%d}
+#[derive(Debug)]
+pub struct RowTemplate(Vec<Part>);
+
+impl RowTemplate {
+	pub fn new(template: &str) -> anyhow::Result<Self> {
+		let mut parts = vec![];
+		let mut last = 0;
+		for cap in PLACEHOLDER.captures_iter(template) {
+			let all = cap.get(0).unwrap();
+			parts.push(Part::Text(template[last..all.start()].to_owned()));
+			let field = cap[1].trim().to_owned();
+			match cap.get(2) {
+				Some(format) => {
+					if StrftimeItems::new(format.as_str()).any(|item| item == Item::Error) {
+						anyhow::bail!("template date format {} error", format.as_str());
+					}
+					parts.push(Part::Date(field, format.as_str().to_owned()));
+				}
+				None => parts.push(Part::Field(field)),
+			}
+			last = all.end();
+		}
+		parts.push(Part::Text(template[last..].to_owned()));
+		if parts.iter().all(|part| matches!(part, Part::Text(s) if s.is_empty())) {
+			anyhow::bail!("template is empty");
+		}
+		Ok(Self(parts))
+	}
+
+	// none if a field is missing
+	pub fn render(&self, row: &Row) -> Option<String> {
+		let mut name = String::new();
+		for part in self.0.iter() {
+			match part {
+				Part::Text(s) => name.push_str(s),
+				Part::Field(field) => match row.get(field)? {
+					serde_json::Value::Null => return None,
+					serde_json::Value::String(s) => name.push_str(s),
+					other => name.push_str(&other.to_string()),
+				},
+				Part::Date(field, format) => {
+					let millis = to_timestamp(row.get(field)?, None)?;
+					let date = chrono::DateTime::from_timestamp_millis(millis)?;
+					name.push_str(&date.format(format).to_string());
+				}
+			}
+		}
+		Some(name)
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::RowTemplate;
+
+	#[test]
+	fn test_row_template() -> anyhow::Result<()> {
+		let template = RowTemplate::new("logs-{service}-{ts:%Y.%m.%d}")?;
+		let row: Row = serde_json::from_value(json!({"service": "api", "ts": 1719792000}))?;
+		assert_eq!(template.render(&row), Some("logs-api-2024.07.01".to_owned()));
+		let row: Row = serde_json::from_value(json!({"service": "api"}))?;
+		assert_eq!(template.render(&row), None);
+		assert!(RowTemplate::new("logs-{ts:%Q}").is_err());
+		assert!(RowTemplate::new("").is_err());
+		Ok(())
+	}
+}
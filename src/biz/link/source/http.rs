@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+use tracing::info;
+use tracing::instrument;
+
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::Source;
+
+lazy_static! {
+	// running task id to ingest buffer
+	static ref INGEST: Mutex<HashMap<i64, mpsc::Sender<CoreMsg>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IngestError {
+	// task not running or source is not http
+	NotRunning,
+	// buffer can not hold the request
+	Full,
+	// more records than the buffer, never fit
+	TooLarge,
+}
+
+// push records into task, all or nothing
+pub fn ingest(task_id: i64, records: Vec<String>) -> Result<usize, IngestError> {
+	let sender = INGEST.lock().unwrap().get(&task_id).cloned().ok_or(IngestError::NotRunning)?;
+	let n = records.len();
+	if n == 0 {
+		return Ok(0);
+	}
+	if n > sender.max_capacity() {
+		return Err(IngestError::TooLarge);
+	}
+	let permits = sender.try_reserve_many(n).map_err(|err| match err {
+		TrySendError::Full(_) => IngestError::Full,
+		TrySendError::Closed(_) => IngestError::NotRunning,
+	})?;
+	let now = chrono::Utc::now().timestamp_millis();
+	for (permit, raw_msg) in permits.zip(records) {
+		permit.send(CoreMsg::default().with_raw_msg(raw_msg).with_timestamp(Some(now)));
+	}
+	Ok(n)
+}
+
+// json document, json array or ndjson into records
+pub fn split_body(body: &str) -> anyhow::Result<Vec<String>> {
+	let body = body.trim();
+	if body.is_empty() {
+		return Ok(vec![]);
+	}
+	if let Ok(val) = serde_json::from_str::<serde_json::Value>(body) {
+		return Ok(match val {
+			serde_json::Value::Array(items) => items.iter().map(|item| item.to_string()).collect(),
+			other => vec![other.to_string()],
+		});
+	}
+	body.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.trim().is_empty())
+		.map(|(i, line)| {
+			serde_json::from_str::<serde_json::Value>(line)
+				.map(|val| val.to_string())
+				.map_err(|err| anyhow::anyhow!("line {} is not json {}", i + 1, err))
+		})
+		.collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct HttpSourceArg {
+	// buffered records, request get 429 when full and 413 with more records than it
+	#[serde(default = "default_buffer")]
+	buffer: usize,
+}
+
+fn default_buffer() -> usize {
+	1024
+}
+
+// records pushed by POST /ingest/:task_id
+pub struct HttpSource {
+	arg: HttpSourceArg,
+	task_id: AtomicI64,
+}
+
+impl HttpSource {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<HttpSource> {
+		let arg: HttpSourceArg =
+			if val.is_null() { from_val(&serde_json::json!({}))? } else { from_val(val)? };
+		if arg.buffer == 0 {
+			anyhow::bail!("http source buffer must larger than 0");
+		}
+		Ok(Self { arg, task_id: AtomicI64::new(0) })
+	}
+
+	// task id of the ingest path
+	pub fn bind(&self, task_id: i64) {
+		self.task_id.store(task_id, Ordering::Relaxed);
+	}
+}
+
+// remove task from ingest when source stop or cancel
+struct Registered(i64);
+
+impl Drop for Registered {
+	fn drop(&mut self) {
+		INGEST.lock().unwrap().remove(&self.0);
+	}
+}
+
+impl Source for HttpSource {
+	#[instrument(skip(self, s))]
+	async fn source(&self, s: mpsc::Sender<CoreMsg>) -> anyhow::Result<()> {
+		let task_id = self.task_id.load(Ordering::Relaxed);
+		let (sender, mut r) = mpsc::channel(self.arg.buffer);
+		INGEST.lock().unwrap().insert(task_id, sender);
+		let _registered = Registered(task_id);
+		info!("start http source POST /ingest/{}", task_id);
+
+		while let Some(msg) = r.recv().await {
+			s.send(msg).await?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::ingest;
+	use super::split_body;
+	use super::HttpSource;
+	use super::IngestError;
+	use crate::biz::link::source::Source;
+
+	#[test]
+	fn test_split_body() -> anyhow::Result<()> {
+		assert_eq!(split_body(r#"{"a": 1}"#)?, vec![r#"{"a":1}"#]);
+		assert_eq!(split_body("[{\"a\": 1}, {\"a\": 2}]")?, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+		assert_eq!(split_body("{\"a\": 1}\n\n{\"a\": 2}\n")?, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+		assert!(split_body("{\"a\": 1}\nbad").is_err());
+		assert!(split_body("  ")?.is_empty());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_ingest() -> anyhow::Result<()> {
+		let source = HttpSource::new(&json!({"buffer": 2}))?;
+		source.bind(8001);
+		assert_eq!(ingest(8001, vec!["{}".to_owned()]), Err(IngestError::NotRunning));
+
+		let (s, mut r) = mpsc::channel(1);
+		let task = tokio::spawn(async move { source.source(s).await });
+		while ingest(8001, vec![]).is_err() {
+			tokio::task::yield_now().await;
+		}
+
+		assert_eq!(ingest(8001, vec!["1".to_owned(), "2".to_owned()]), Ok(2));
+		// one in task channel, one in buffer, all or nothing
+		assert_eq!(r.recv().await.unwrap().get_raw_msg(), "1");
+		// more than buffer never fit
+		assert_eq!(
+			ingest(8001, vec!["3".to_owned(), "4".to_owned(), "5".to_owned()]),
+			Err(IngestError::TooLarge)
+		);
+		// without consumer the buffer get full
+		let full = loop {
+			match ingest(8001, vec!["6".to_owned()]) {
+				Ok(_) => tokio::task::yield_now().await,
+				Err(err) => break err,
+			}
+		};
+		assert_eq!(full, IngestError::Full);
+
+		task.abort();
+		let _ = task.await;
+		assert_eq!(ingest(8001, vec![]), Err(IngestError::NotRunning));
+		Ok(())
+	}
+}
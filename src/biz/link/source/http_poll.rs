@@ -0,0 +1,550 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sqlx::MySqlPool;
+
+use tokio::sync::mpsc;
+
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::core::Ack;
+use crate::core::AckKind;
+use crate::core::Acked;
+use crate::core::CoreMsg;
+use crate::model::task_cursor::TaskCursor;
+use crate::util::from_val;
+
+use super::Source;
+
+#[derive(Debug, Deserialize)]
+struct HttpPollArg {
+	url: String,
+	#[serde(default)]
+	headers: HashMap<String, String>,
+	// seconds between polls after the last page
+	#[serde(default = "default_interval")]
+	interval: u64,
+	// request timeout seconds
+	#[serde(default = "default_timeout")]
+	timeout: u64,
+	// path of records in body like data.items, empty is the body
+	#[serde(default)]
+	records: String,
+	#[serde(default)]
+	cursor: Option<CursorArg>,
+	#[serde(default)]
+	mark: Option<MarkArg>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CursorArg {
+	// path of next cursor in body like meta.next
+	path: String,
+	// query param the cursor send with
+	param: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MarkArg {
+	// path of an increasing field in record like id or updated_at
+	path: String,
+	// query param the high-water mark send with, records not past it are skipped anyway
+	#[serde(default)]
+	param: Option<String>,
+}
+
+fn default_interval() -> u64 {
+	60
+}
+
+fn default_timeout() -> u64 {
+	30
+}
+
+// dot path with array index like data.items or $.data.0.items
+fn select<'a>(val: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+	let path = path.trim_start_matches('$').trim_start_matches('.');
+	if path.is_empty() {
+		return Some(val);
+	}
+	path.split('.').try_fold(val, |val, key| match val {
+		serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+		serde_json::Value::Object(obj) => obj.get(key),
+		_ => None,
+	})
+}
+
+// cursor as query value, string without quote
+fn cursor_of(val: &serde_json::Value) -> Option<String> {
+	match val {
+		serde_json::Value::Null => None,
+		serde_json::Value::String(s) if s.is_empty() => None,
+		serde_json::Value::String(s) => Some(s.clone()),
+		other => Some(other.to_string()),
+	}
+}
+
+// record field past the high-water mark, numbers and strings compare by value
+fn past_mark(val: &serde_json::Value, mark: Option<&serde_json::Value>) -> bool {
+	match (val, mark) {
+		(serde_json::Value::Number(v), Some(serde_json::Value::Number(m))) => {
+			v.as_f64().partial_cmp(&m.as_f64()) == Some(Ordering::Greater)
+		}
+		(serde_json::Value::String(v), Some(serde_json::Value::String(m))) => v > m,
+		// no mark yet or not comparable
+		_ => true,
+	}
+}
+
+// where the poll is, saved as task_cursor position
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct PollPos {
+	cursor: Option<String>,
+	mark: Option<serde_json::Value>,
+}
+
+// a polled page, with emitted records it waits for them to be sunk
+#[derive(Debug)]
+struct Page {
+	seq: u64,
+	pos: PollPos,
+	sunk: bool,
+}
+
+// pages in poll order, the position only moves over leading sunk pages
+#[derive(Debug, Default)]
+struct Pages {
+	pending: VecDeque<Page>,
+}
+
+impl Pages {
+	fn page(&mut self, seq: u64, pos: PollPos, emitted: bool) {
+		self.pending.push_back(Page { seq, pos, sunk: !emitted });
+	}
+
+	// records of a page settled, a failed write polls again from the last sunk page
+	fn settle(&mut self, acked: &Acked) -> anyhow::Result<()> {
+		let Some(page) = self.pending.iter_mut().find(|p| p.seq.to_string() == acked.id) else {
+			return Ok(());
+		};
+		match acked.kind {
+			AckKind::Done => page.sunk = true,
+			AckKind::Reject => {
+				warn!("http poll records of page {:?} rejected, skip them", page.pos);
+				page.sunk = true;
+			}
+			AckKind::Retry => anyhow::bail!("http poll records of page {:?} failed", page.pos),
+		}
+		Ok(())
+	}
+
+	// position after leading sunk pages
+	fn advance(&mut self) -> Option<PollPos> {
+		let mut advanced = None;
+		while self.pending.front().is_some_and(|p| p.sunk) {
+			advanced = self.pending.pop_front().map(|p| p.pos);
+		}
+		advanced
+	}
+}
+
+// poll a paginated api, cursor persist in task_cursor
+pub struct HttpPollSource {
+	arg: HttpPollArg,
+	// task id and conn, cursor only in memory without them
+	bind: OnceLock<(i64, MySqlPool)>,
+}
+
+impl HttpPollSource {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<HttpPollSource> {
+		let arg: HttpPollArg = from_val(val)?;
+		reqwest::Url::parse(&arg.url).with_context(|| format!("http poll url {}", arg.url))?;
+		Ok(Self { arg, bind: OnceLock::new() })
+	}
+
+	pub fn bind(&self, task_id: i64, conn: MySqlPool) {
+		let _ = self.bind.set((task_id, conn));
+	}
+
+	fn client(&self) -> anyhow::Result<reqwest::Client> {
+		let mut headers = reqwest::header::HeaderMap::new();
+		for (k, v) in self.arg.headers.iter() {
+			headers.insert(
+				reqwest::header::HeaderName::from_bytes(k.as_bytes())?,
+				reqwest::header::HeaderValue::from_str(v)?,
+			);
+		}
+		let client = reqwest::Client::builder()
+			.default_headers(headers)
+			.timeout(Duration::from_secs(self.arg.timeout))
+			.build()?;
+		Ok(client)
+	}
+
+	// a bare cursor without mark, json of both with it
+	async fn load_pos(&self) -> anyhow::Result<PollPos> {
+		let position = match self.bind.get() {
+			Some((task_id, conn)) => TaskCursor::fetch(conn, *task_id).await?,
+			None => None,
+		};
+		let Some(position) = position else {
+			return Ok(PollPos::default());
+		};
+		if self.arg.mark.is_some() {
+			if let Ok(pos) = serde_json::from_str::<PollPos>(&position) {
+				return Ok(pos);
+			}
+		}
+		Ok(PollPos { cursor: Some(position), mark: None })
+	}
+
+	async fn save_pos(&self, pos: &PollPos) -> anyhow::Result<()> {
+		let position = match &self.arg.mark {
+			Some(_) => Some(serde_json::to_string(pos)?),
+			None => pos.cursor.clone(),
+		};
+		match (self.bind.get(), position) {
+			(Some((task_id, conn)), Some(position)) => {
+				TaskCursor::save(conn, *task_id, &position).await
+			}
+			_ => Ok(()),
+		}
+	}
+
+	// one page, return records and next cursor
+	async fn fetch(
+		&self,
+		client: &reqwest::Client,
+		pos: &PollPos,
+	) -> anyhow::Result<(Vec<serde_json::Value>, Option<String>)> {
+		let mut req = client.get(&self.arg.url);
+		if let (Some(arg), Some(cursor)) = (&self.arg.cursor, &pos.cursor) {
+			req = req.query(&[(arg.param.as_str(), cursor)]);
+		}
+		let mark = pos.mark.as_ref().and_then(cursor_of);
+		if let (Some(param), Some(mark)) =
+			(self.arg.mark.as_ref().and_then(|arg| arg.param.as_ref()), mark)
+		{
+			req = req.query(&[(param.as_str(), mark)]);
+		}
+		let body: serde_json::Value = req.send().await?.error_for_status()?.json().await?;
+
+		let records = match select(&body, &self.arg.records) {
+			Some(serde_json::Value::Array(items)) => items.clone(),
+			Some(serde_json::Value::Null) | None => vec![],
+			Some(other) => vec![other.clone()],
+		};
+		let next =
+			self.arg.cursor.as_ref().and_then(|arg| select(&body, &arg.path)).and_then(cursor_of);
+		Ok((records, next))
+	}
+}
+
+impl Source for HttpPollSource {
+	// next page at once, wait interval after the last page or error
+	#[instrument(skip(self, s))]
+	async fn source(&self, s: mpsc::Sender<CoreMsg>) -> anyhow::Result<()> {
+		let client = self.client()?;
+		let mut saved = self.load_pos().await?;
+		let mut pos = saved.clone();
+		info!("start http poll {} from {:?}", self.arg.url, pos);
+
+		let (acks, mut r) = mpsc::unbounded_channel();
+		let mut pages = Pages::default();
+		let mut seq = 0;
+		// records of the last page, not emitted again when it is polled again
+		let mut seen = HashSet::new();
+		loop {
+			let more = match self.fetch(&client, &pos).await {
+				Ok((records, next)) => {
+					debug!("http poll {} records {} next {:?}", self.arg.url, records.len(), next);
+					let next = next.filter(|next| Some(next) != pos.cursor.as_ref());
+					let now = chrono::Utc::now().timestamp_millis();
+					let ack = Ack::new(seq.to_string(), acks.clone());
+					let since = pos.mark.clone();
+					let mut page = HashSet::new();
+					let mut emitted = false;
+					for record in records {
+						let raw_msg = record.to_string();
+						if !page.insert(raw_msg.clone()) || seen.contains(&raw_msg) {
+							continue;
+						}
+						if let Some(arg) = &self.arg.mark {
+							let val = select(&record, &arg.path).filter(|val| !val.is_null());
+							if let Some(val) = val {
+								if !past_mark(val, since.as_ref()) {
+									continue;
+								}
+								if past_mark(val, pos.mark.as_ref()) {
+									pos.mark = Some(val.clone());
+								}
+							}
+						}
+						let msg = CoreMsg::default()
+							.with_raw_msg(raw_msg)
+							.with_timestamp(Some(now))
+							.with_ack(Some(ack.clone()));
+						s.send(msg).await?;
+						emitted = true;
+					}
+					// handed over to the records
+					ack.done();
+					// last page keep the cursor, poll it again after interval
+					seen = match next {
+						Some(_) => HashSet::new(),
+						None => page,
+					};
+					let more = next.is_some();
+					if next.is_some() {
+						pos.cursor = next;
+					}
+					pages.page(seq, pos.clone(), emitted);
+					seq += 1;
+					more
+				}
+				Err(err) => {
+					error!("http poll {} error {:?}", self.arg.url, err);
+					false
+				}
+			};
+
+			// save the position once records are sunk, acks also settle while waiting
+			let wait = if more { Duration::ZERO } else { Duration::from_secs(self.arg.interval) };
+			let sleep = tokio::time::sleep(wait);
+			tokio::pin!(sleep);
+			loop {
+				tokio::select! {
+					biased;
+					Some(acked) = r.recv() => match pages.settle(&acked) {
+						Ok(()) => {
+							if let Some(advanced) = pages.advance().filter(|p| *p != saved) {
+								self.save_pos(&advanced).await?;
+								saved = advanced;
+							}
+						}
+						Err(err) => {
+							warn!("{:?}, poll again from {:?}", err, saved);
+							pos = saved.clone();
+							pages = Pages::default();
+							seen.clear();
+						}
+					},
+					_ = &mut sleep => break,
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashMap;
+	use std::collections::HashSet;
+	use std::sync::atomic::AtomicI64;
+	use std::sync::atomic::Ordering;
+	use std::time::Duration;
+
+	use axum::extract::Query;
+	use axum::routing::get;
+	use axum::Json;
+	use axum::Router;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::past_mark;
+	use super::select;
+	use super::HttpPollSource;
+	use super::Pages;
+	use super::PollPos;
+	use crate::biz::link::source::Source;
+	use crate::core::AckKind;
+	use crate::core::Acked;
+
+	#[test]
+	fn test_select() {
+		let body = json!({"data": {"items": [{"id": 1}], "next": 2}});
+		assert_eq!(select(&body, "data.items.0.id"), Some(&json!(1)));
+		assert_eq!(select(&body, "$.data.next"), Some(&json!(2)));
+		assert_eq!(select(&body, ""), Some(&body));
+		assert_eq!(select(&body, "data.none"), None);
+	}
+
+	// three pages by cursor, the last page without next
+	async fn page(Query(q): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+		let page = q.get("after").and_then(|p| p.parse::<i64>().ok()).unwrap_or(0);
+		let next = if page < 2 { json!(page + 1) } else { json!(null) };
+		Json(
+			json!({"data": {"items": [{"page": page, "i": 0}, {"page": page, "i": 1}]}, "next": next}),
+		)
+	}
+
+	#[tokio::test]
+	async fn test_http_poll() -> anyhow::Result<()> {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let server = tokio::spawn(async move {
+			axum::serve(listener, Router::new().route("/items", get(page))).await
+		});
+
+		let source = HttpPollSource::new(&json!({
+			"url": format!("http://{}/items", addr),
+			"records": "data.items",
+			"cursor": {"path": "next", "param": "after"},
+			"interval": 3600
+		}))?;
+		let (s, mut r) = mpsc::channel(10);
+		let task = tokio::spawn(async move { source.source(s).await });
+
+		let mut got = vec![];
+		for _ in 0..6 {
+			let msg = r.recv().await.unwrap();
+			got.push(serde_json::from_str::<serde_json::Value>(msg.get_raw_msg())?);
+			msg.done();
+		}
+		assert_eq!(got[0], json!({"page": 0, "i": 0}));
+		assert_eq!(got[5], json!({"page": 2, "i": 1}));
+
+		task.abort();
+		server.abort();
+		Ok(())
+	}
+
+	#[test]
+	fn test_past_mark() {
+		assert!(past_mark(&json!(3), Some(&json!(2.5))));
+		assert!(!past_mark(&json!(2), Some(&json!(2))));
+		assert!(past_mark(&json!("2024-01-02"), Some(&json!("2024-01-01"))));
+		assert!(past_mark(&json!(1), None));
+	}
+
+	#[test]
+	fn test_http_poll_pages() {
+		let pos = |cursor: &str| PollPos { cursor: Some(cursor.to_owned()), mark: None };
+		let acked = |id: &str, kind| Acked { id: id.to_owned(), kind };
+		let mut pages = Pages::default();
+		pages.page(0, pos("1"), true);
+		pages.page(1, pos("2"), false);
+		pages.page(2, pos("3"), true);
+		// nothing sunk yet
+		assert_eq!(pages.advance(), None);
+		pages.settle(&acked("2", AckKind::Done)).unwrap();
+		assert_eq!(pages.advance(), None);
+		pages.settle(&acked("0", AckKind::Reject)).unwrap();
+		assert_eq!(pages.advance(), Some(pos("3")));
+		pages.page(3, pos("4"), true);
+		assert!(pages.settle(&acked("3", AckKind::Retry)).is_err());
+	}
+
+	static POLLED: AtomicI64 = AtomicI64::new(0);
+
+	// the last page get a new record after the first poll of it
+	async fn growing(Query(q): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+		let page = q.get("after").and_then(|p| p.parse::<i64>().ok()).unwrap_or(0);
+		if page < 1 {
+			return Json(json!({"items": [{"page": page, "i": 0}], "next": page + 1}));
+		}
+		let polled = POLLED.fetch_add(1, Ordering::Relaxed);
+		let items: Vec<_> =
+			(0..(polled + 1).min(2)).map(|i| json!({"page": page, "i": i})).collect();
+		Json(json!({"items": items, "next": null}))
+	}
+
+	// records received within wait
+	async fn drain(
+		r: &mut mpsc::Receiver<crate::core::CoreMsg>,
+		wait: Duration,
+	) -> Vec<serde_json::Value> {
+		let deadline = tokio::time::Instant::now() + wait;
+		let mut got = vec![];
+		while let Ok(Some(msg)) = tokio::time::timeout_at(deadline, r.recv()).await {
+			got.push(serde_json::from_str(msg.get_raw_msg()).unwrap());
+			msg.done();
+		}
+		got
+	}
+
+	#[tokio::test]
+	async fn test_http_poll_last_page() -> anyhow::Result<()> {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let server = tokio::spawn(async move {
+			axum::serve(listener, Router::new().route("/items", get(growing))).await
+		});
+
+		let source = HttpPollSource::new(&json!({
+			"url": format!("http://{}/items", addr),
+			"records": "items",
+			"cursor": {"path": "next", "param": "after"},
+			"interval": 1
+		}))?;
+		let (s, mut r) = mpsc::channel(10);
+		let task = tokio::spawn(async move { source.source(s).await });
+
+		// the last page polled three times, its first record emitted once
+		let got = drain(&mut r, Duration::from_millis(2500)).await;
+		assert!(POLLED.load(Ordering::Relaxed) >= 3);
+		assert_eq!(
+			got,
+			vec![
+				json!({"page": 0, "i": 0}),
+				json!({"page": 1, "i": 0}),
+				json!({"page": 1, "i": 1})
+			]
+		);
+
+		task.abort();
+		server.abort();
+		Ok(())
+	}
+
+	static LATEST: AtomicI64 = AtomicI64::new(0);
+
+	// latest two records without cursor, one more on every poll
+	async fn latest() -> Json<serde_json::Value> {
+		let last = LATEST.fetch_add(1, Ordering::Relaxed) + 2;
+		Json(json!([{"id": last - 1, "v": last}, {"id": last, "v": last}]))
+	}
+
+	#[tokio::test]
+	async fn test_http_poll_mark() -> anyhow::Result<()> {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let addr = listener.local_addr()?;
+		let server = tokio::spawn(async move {
+			axum::serve(listener, Router::new().route("/latest", get(latest))).await
+		});
+
+		let source = HttpPollSource::new(&json!({
+			"url": format!("http://{}/latest", addr),
+			"mark": {"path": "id"},
+			"interval": 1
+		}))?;
+		let (s, mut r) = mpsc::channel(10);
+		let task = tokio::spawn(async move { source.source(s).await });
+
+		// the record of id 2 changes v on the next poll, past mark keeps it once
+		let got = drain(&mut r, Duration::from_millis(2500)).await;
+		let ids: Vec<_> = got.iter().map(|v| v["id"].as_i64().unwrap()).collect();
+		assert_eq!(ids.len(), ids.iter().collect::<HashSet<_>>().len());
+		assert_eq!(ids, (1..=ids.len() as i64).collect::<Vec<_>>());
+		assert!(ids.len() >= 3);
+
+		task.abort();
+		server.abort();
+		Ok(())
+	}
+}
@@ -103,7 +103,9 @@ impl Source for KafkaSource {
 					)
 				})?
 				.to_string();
-			let _ = s.send(CoreMsg::default().with_raw_msg(raw_msg)).await;
+			let timestamp = msg.timestamp().to_millis();
+			let _ =
+				s.send(CoreMsg::default().with_raw_msg(raw_msg).with_timestamp(timestamp)).await;
 		}
 		Ok(())
 	}
@@ -1,20 +1,42 @@
+pub mod amqp;
 pub mod empty;
+pub mod http;
+pub mod http_poll;
 pub mod kafka;
+pub mod mysql_binlog;
+pub mod nats;
+pub mod pipe;
+pub mod redis;
 
 use enum_dispatch::enum_dispatch;
 
 use tokio::sync::mpsc;
 
+use amqp::AmqpSource;
 use kafka::KafkaSource;
+use mysql_binlog::MySqlBinlogSource;
+use nats::NatsSource;
+use pipe::PipeSource;
+use redis::RedisStreamSource;
 
 use empty::EmptySource;
 
+use http::HttpSource;
+use http_poll::HttpPollSource;
+
 use crate::core::CoreMsg;
 
 #[enum_dispatch]
 pub enum SourceEnum {
+	AmqpSource,
 	EmptySource,
+	HttpSource,
+	HttpPollSource,
 	KafkaSource,
+	MySqlBinlogSource,
+	NatsSource,
+	PipeSource,
+	RedisStreamSource,
 }
 
 #[allow(async_fn_in_trait)]
@@ -27,6 +49,13 @@ pub fn get_source(name: &str, val: &serde_json::Value) -> anyhow::Result<SourceE
 	match name.to_lowercase().as_str() {
 		"kafka" => Ok(KafkaSource::new(val)?.into()),
 		"empty" => Ok(EmptySource::new(val)?.into()),
+		"http" => Ok(HttpSource::new(val)?.into()),
+		"http_poll" => Ok(HttpPollSource::new(val)?.into()),
+		"nats" => Ok(NatsSource::new(val)?.into()),
+		"pipe" => Ok(PipeSource::new(val)?.into()),
+		"amqp" => Ok(AmqpSource::new(val)?.into()),
+		"mysql_binlog" => Ok(MySqlBinlogSource::new(val)?.into()),
+		"redis_stream" => Ok(RedisStreamSource::new(val)?.into()),
 		other => anyhow::bail!("unknown data source {}", other),
 	}
 }
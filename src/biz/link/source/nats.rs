@@ -0,0 +1,346 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use async_nats::jetstream;
+use async_nats::jetstream::consumer::pull;
+use async_nats::jetstream::consumer::AckPolicy;
+use async_nats::jetstream::consumer::DeliverPolicy;
+use async_nats::ConnectOptions;
+use async_nats::ServerAddr;
+
+use futures_util::stream::select_all;
+use futures_util::StreamExt;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+
+use crate::core::Ack;
+use crate::core::AckKind;
+use crate::core::Acked;
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::Source;
+
+// connection of nats source and sink
+#[derive(Debug, Clone, Deserialize)]
+pub struct NatsConnArg {
+	// nats://127.0.0.1:4222, servers split by comma
+	pub url: String,
+	#[serde(default)]
+	pub user: Option<String>,
+	#[serde(default)]
+	pub password: Option<String>,
+	#[serde(default)]
+	pub token: Option<String>,
+}
+
+impl NatsConnArg {
+	// reconnect after broker restart is done by the client
+	pub async fn connect(&self) -> anyhow::Result<async_nats::Client> {
+		let mut opts = ConnectOptions::new().name("hydrogen").retry_on_initial_connect();
+		if let (Some(user), Some(password)) = (&self.user, &self.password) {
+			opts = opts.user_and_password(user.clone(), password.clone());
+		}
+		if let Some(token) = &self.token {
+			opts = opts.token(token.clone());
+		}
+		let servers = self
+			.url
+			.split(',')
+			.map(|url| url.trim().parse::<ServerAddr>())
+			.collect::<Result<Vec<_>, _>>()
+			.with_context(|| format!("nats url {}", self.url))?;
+		let client =
+			opts.connect(servers).await.with_context(|| format!("connect nats {}", self.url))?;
+		Ok(client)
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct NatsSourceArg {
+	#[serde(flatten)]
+	conn: NatsConnArg,
+	subjects: Vec<String>,
+	// core nats queue group, subscribers of a group share messages
+	#[serde(default)]
+	queue: Option<String>,
+	// consume by jetstream durable consumer and ack after sink
+	#[serde(default)]
+	jetstream: Option<JetStreamArg>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JetStreamArg {
+	stream: String,
+	durable: String,
+	#[serde(default)]
+	deliver: Deliver,
+	// seconds before an unacked message is redelivered
+	#[serde(default = "default_ack_wait")]
+	ack_wait: u64,
+	#[serde(default = "default_max_ack_pending")]
+	max_ack_pending: i64,
+	// deliveries of a failed message, -1 without limit
+	#[serde(default = "default_max_deliver")]
+	max_deliver: i64,
+	// seconds before a failed message is redelivered, doubled every delivery
+	#[serde(default = "default_retry_delay")]
+	retry_delay: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Deliver {
+	#[default]
+	All,
+	New,
+	Last,
+}
+
+fn default_ack_wait() -> u64 {
+	30
+}
+
+fn default_max_ack_pending() -> i64 {
+	1024
+}
+
+fn default_max_deliver() -> i64 {
+	-1
+}
+
+fn default_retry_delay() -> u64 {
+	1
+}
+
+// the longest wait of a failed message
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+// wait before the next delivery, doubled by deliveries so far
+fn retry_delay(base: u64, delivered: i64) -> Duration {
+	let exp = delivered.clamp(1, 32) as u32 - 1;
+	Duration::from_secs(base.saturating_mul(1 << exp)).min(MAX_RETRY_DELAY)
+}
+
+// ack id of a jetstream message, reply subject and the delay of nak
+fn ack_id(reply: &str, delay: Duration) -> String {
+	format!("{} {}", reply, delay.as_nanos())
+}
+
+// jetstream ack of the result, nak redeliver the message after delay until max_deliver
+fn ack_payload(kind: AckKind, delay: &str) -> String {
+	match kind {
+		AckKind::Done => "+ACK".to_owned(),
+		AckKind::Retry => format!("-NAK {{\"delay\": {}}}", delay),
+		AckKind::Reject => "+TERM".to_owned(),
+	}
+}
+
+// publish acks to the reply subjects of settled messages
+async fn settle<F, Fut, E>(mut r: mpsc::UnboundedReceiver<Acked>, publish: F) -> anyhow::Result<()>
+where
+	F: Fn(String, String) -> Fut,
+	Fut: Future<Output = Result<(), E>>,
+	E: std::fmt::Debug,
+{
+	while let Some(acked) = r.recv().await {
+		let (reply, delay) = acked.id.split_once(' ').unwrap_or((&acked.id, "0"));
+		if let Err(err) = publish(reply.to_owned(), ack_payload(acked.kind, delay)).await {
+			// redelivered after ack wait
+			error!("nats ack error {:?}", err);
+		}
+	}
+	Ok(())
+}
+
+// subscribe subjects, or read a jetstream durable consumer with explicit ack
+pub struct NatsSource {
+	arg: NatsSourceArg,
+}
+
+impl NatsSource {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<NatsSource> {
+		let arg: NatsSourceArg = from_val(val)?;
+		if arg.subjects.is_empty() {
+			anyhow::bail!("nats source need at least one subject");
+		}
+		if arg.jetstream.is_some() && arg.subjects.len() > 1 {
+			anyhow::bail!("nats jetstream consumer filter one subject");
+		}
+		Ok(Self { arg })
+	}
+
+	async fn subscribe(
+		&self,
+		client: async_nats::Client,
+		s: mpsc::Sender<CoreMsg>,
+	) -> anyhow::Result<()> {
+		let mut subs = vec![];
+		for subject in self.arg.subjects.iter() {
+			let sub = match &self.arg.queue {
+				Some(queue) => client.queue_subscribe(subject.clone(), queue.clone()).await?,
+				None => client.subscribe(subject.clone()).await?,
+			};
+			subs.push(sub);
+		}
+		let mut messages = select_all(subs);
+		while let Some(msg) = messages.next().await {
+			let raw_msg = String::from_utf8_lossy(&msg.payload).into_owned();
+			let now = chrono::Utc::now().timestamp_millis();
+			s.send(CoreMsg::new(raw_msg).with_timestamp(Some(now))).await?;
+		}
+		Ok(())
+	}
+
+	async fn consume(
+		&self,
+		js: &JetStreamArg,
+		client: async_nats::Client,
+		s: mpsc::Sender<CoreMsg>,
+	) -> anyhow::Result<()> {
+		let context = jetstream::new(client.clone());
+		let stream = context
+			.get_stream(&js.stream)
+			.await
+			.with_context(|| format!("get nats stream {}", js.stream))?;
+		let config = pull::Config {
+			durable_name: Some(js.durable.clone()),
+			filter_subject: self.arg.subjects[0].clone(),
+			ack_policy: AckPolicy::Explicit,
+			deliver_policy: match js.deliver {
+				Deliver::All => DeliverPolicy::All,
+				Deliver::New => DeliverPolicy::New,
+				Deliver::Last => DeliverPolicy::Last,
+			},
+			ack_wait: Duration::from_secs(js.ack_wait),
+			max_ack_pending: js.max_ack_pending,
+			max_deliver: js.max_deliver,
+			..Default::default()
+		};
+		let consumer = stream
+			.get_or_create_consumer(&js.durable, config)
+			.await
+			.with_context(|| format!("get nats consumer {}", js.durable))?;
+
+		let (acks, r) = mpsc::unbounded_channel::<Acked>();
+		let ack = settle(r, |reply, payload| {
+			let client = client.clone();
+			async move { client.publish(reply, payload.into()).await }
+		});
+		let read = async move {
+			let mut messages = consumer.messages().await?;
+			while let Some(msg) = messages.next().await {
+				let msg = msg?;
+				let delivered = msg.info().map(|info| info.delivered).unwrap_or(1);
+				let delay = retry_delay(js.retry_delay, delivered);
+				let ack =
+					msg.reply.as_ref().map(|reply| Ack::new(ack_id(reply, delay), acks.clone()));
+				let raw_msg = String::from_utf8_lossy(&msg.payload).into_owned();
+				let now = chrono::Utc::now().timestamp_millis();
+				s.send(CoreMsg::new(raw_msg).with_timestamp(Some(now)).with_ack(ack)).await?;
+			}
+			anyhow::Ok(())
+		};
+		tokio::try_join!(read, ack)?;
+		Ok(())
+	}
+}
+
+impl Source for NatsSource {
+	#[instrument(skip(self, s))]
+	async fn source(&self, s: mpsc::Sender<CoreMsg>) -> anyhow::Result<()> {
+		info!("start nats source {:?}", self.arg.subjects);
+		let client = self.arg.conn.connect().await?;
+		match &self.arg.jetstream {
+			Some(js) => self.consume(js, client, s).await,
+			None => self.subscribe(client, s).await,
+		}
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use tokio::sync::mpsc;
+
+	use super::ack_id;
+	use super::retry_delay;
+	use super::settle;
+	use super::NatsSource;
+	use crate::biz::link::sink::batch::SinkErrors;
+	use crate::core::Ack;
+	use crate::core::CoreMsg;
+
+	#[test]
+	fn test_nats_source_conf() -> anyhow::Result<()> {
+		let source = NatsSource::new(&json!({
+			"url": "nats://127.0.0.1:4222",
+			"subjects": ["events.>"],
+			"jetstream": {"stream": "EVENTS", "durable": "hydrogen", "deliver": "new"}
+		}))?;
+		assert_eq!(source.arg.jetstream.unwrap().ack_wait, 30);
+
+		assert!(NatsSource::new(&json!({"url": "nats://127.0.0.1", "subjects": []})).is_err());
+		assert!(NatsSource::new(&json!({
+			"url": "nats://127.0.0.1",
+			"subjects": ["a", "b"],
+			"jetstream": {"stream": "S", "durable": "d"}
+		}))
+		.is_err());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_nats_settle() -> anyhow::Result<()> {
+		let (acks, r) = mpsc::unbounded_channel();
+		let msg = |reply: &str| {
+			let rows = serde_json::from_value(json!([{"a": 1}])).unwrap();
+			CoreMsg::default()
+				.with_result(rows)
+				.with_ack(Some(Ack::new(ack_id(reply, retry_delay(1, 3)), acks.clone())))
+		};
+		// sunk, failed in sinker and failed to parse
+		msg("reply.1").done();
+		SinkErrors::default().fail(msg("reply.2"));
+		msg("reply.3").reject();
+		drop(acks);
+
+		let published = Arc::new(Mutex::new(vec![]));
+		settle(r, |reply, payload| {
+			published.lock().unwrap().push((reply, payload));
+			async { anyhow::Ok(()) }
+		})
+		.await?;
+		assert_eq!(
+			*published.lock().unwrap(),
+			[
+				("reply.1".to_owned(), "+ACK".to_owned()),
+				("reply.2".to_owned(), r#"-NAK {"delay": 4000000000}"#.to_owned()),
+				("reply.3".to_owned(), "+TERM".to_owned())
+			]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_nats_retry_delay() {
+		assert_eq!(retry_delay(1, 1).as_secs(), 1);
+		assert_eq!(retry_delay(2, 3).as_secs(), 8);
+		// capped, and deliveries over the shift width
+		assert_eq!(retry_delay(1, 20).as_secs(), 300);
+		assert_eq!(retry_delay(1, i64::MAX).as_secs(), 300);
+		assert_eq!(retry_delay(u64::MAX, 2).as_secs(), 300);
+	}
+}
@@ -0,0 +1,54 @@
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::info;
+use tracing::instrument;
+
+use crate::biz::link::pipe::pipe;
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::Source;
+
+#[derive(Debug, Deserialize)]
+struct PipeSourceArg {
+	// pipe name, same as the upstream pipe sink
+	name: String,
+	// stop upstream tasks when this task stop
+	#[serde(default)]
+	cascade: bool,
+}
+
+// read rows of upstream task in the same process
+pub struct PipeSource {
+	arg: PipeSourceArg,
+}
+
+impl PipeSource {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<PipeSource> {
+		let arg = from_val(val)?;
+		Ok(Self { arg })
+	}
+
+	pub fn get_name(&self) -> &str {
+		&self.arg.name
+	}
+
+	pub fn get_cascade(&self) -> bool {
+		self.arg.cascade
+	}
+}
+
+impl Source for PipeSource {
+	#[instrument(skip(self, s))]
+	async fn source(&self, s: mpsc::Sender<CoreMsg>) -> anyhow::Result<()> {
+		let pipe = pipe(self.get_name());
+		let mut r = pipe.receiver()?;
+		info!("start pipe source {}", self.get_name());
+		while let Some(msg) = r.recv().await {
+			s.send(msg).await?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use redis::aio::ConnectionManager;
+use redis::streams::StreamId;
+use redis::streams::StreamReadOptions;
+use redis::streams::StreamReadReply;
+use redis::AsyncCommands;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::core::Ack;
+use crate::core::AckKind;
+use crate::core::Acked;
+use crate::core::CoreMsg;
+use crate::util::from_val;
+
+use super::Source;
+
+// acked ids of one XACK
+const ACK_BATCH: usize = 256;
+
+// wait before failed entries are read again from pending
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct RedisStreamArg {
+	// redis://127.0.0.1:6379/0
+	url: String,
+	stream: String,
+	group: String,
+	#[serde(default = "default_consumer")]
+	consumer: String,
+	// id the group start from when it is created, $ read new entries only
+	#[serde(default = "default_start")]
+	start: String,
+	// entries of one read
+	#[serde(default = "default_count")]
+	count: usize,
+	#[serde(default = "default_block_ms")]
+	block_ms: usize,
+	// entry field holds the message, empty encode all fields as json
+	#[serde(default)]
+	field: Option<String>,
+}
+
+fn default_consumer() -> String {
+	"hydrogen".to_owned()
+}
+
+fn default_start() -> String {
+	"$".to_owned()
+}
+
+fn default_count() -> usize {
+	100
+}
+
+fn default_block_ms() -> usize {
+	5000
+}
+
+fn to_string(val: &redis::Value) -> Option<String> {
+	redis::from_redis_value::<String>(val).ok()
+}
+
+// ids to XACK, sunk and rejected entries, and whether any entry failed
+fn settled(acked: &mut Vec<Acked>) -> (Vec<String>, bool) {
+	let mut failed = false;
+	let ids = acked
+		.drain(..)
+		.filter(|acked| {
+			failed |= acked.kind == AckKind::Retry;
+			acked.kind != AckKind::Retry
+		})
+		.map(|acked| acked.id)
+		.collect();
+	(ids, failed)
+}
+
+// read a stream by consumer group, entries are acked after the sinker writes them
+pub struct RedisStreamSource {
+	arg: RedisStreamArg,
+	// failed entries stay pending, read pending again
+	retry: AtomicBool,
+}
+
+impl RedisStreamSource {
+	pub fn new(val: &serde_json::Value) -> anyhow::Result<RedisStreamSource> {
+		let arg: RedisStreamArg = from_val(val)?;
+		redis::parse_redis_url(&arg.url)
+			.ok_or_else(|| anyhow::anyhow!("redis stream url {} error", arg.url))?;
+		if arg.count == 0 {
+			anyhow::bail!("redis stream count must larger than 0");
+		}
+		Ok(Self { arg, retry: AtomicBool::new(false) })
+	}
+
+	async fn connect(&self) -> anyhow::Result<ConnectionManager> {
+		let client = redis::Client::open(self.arg.url.as_str())?;
+		let conn = ConnectionManager::new(client)
+			.await
+			.with_context(|| format!("connect redis stream {}", self.arg.stream))?;
+		Ok(conn)
+	}
+
+	// raw message of entry, none if the field is missing
+	fn raw_msg(&self, entry: &StreamId) -> Option<String> {
+		match &self.arg.field {
+			Some(field) => entry.map.get(field).and_then(to_string),
+			None => {
+				let fields = entry
+					.map
+					.iter()
+					.filter_map(|(k, v)| to_string(v).map(|v| (k.clone(), v)))
+					.collect::<HashMap<_, _>>();
+				Some(serde_json::json!(fields).to_string())
+			}
+		}
+	}
+
+	// XREADGROUP from id, > for new entries, others for pending entries of the consumer
+	async fn read(
+		&self,
+		conn: &mut ConnectionManager,
+		id: &str,
+	) -> redis::RedisResult<Vec<StreamId>> {
+		let mut opts = StreamReadOptions::default()
+			.group(&self.arg.group, &self.arg.consumer)
+			.count(self.arg.count);
+		if id == ">" {
+			opts = opts.block(self.arg.block_ms);
+		}
+		let reply: Option<StreamReadReply> =
+			conn.xread_options(&[&self.arg.stream], &[id], &opts).await?;
+		Ok(reply.into_iter().flat_map(|reply| reply.keys).flat_map(|key| key.ids).collect())
+	}
+
+	async fn deliver(
+		&self,
+		entries: Vec<StreamId>,
+		s: &mpsc::Sender<CoreMsg>,
+		acks: &mpsc::UnboundedSender<Acked>,
+	) -> anyhow::Result<()> {
+		for entry in entries {
+			let ack = Ack::new(entry.id.clone(), acks.clone());
+			let Some(raw_msg) = self.raw_msg(&entry) else {
+				warn!("redis stream {} entry {} without message field", self.arg.stream, entry.id);
+				ack.reject();
+				continue;
+			};
+			// entry id start with epoch millis
+			let timestamp = entry.id.split('-').next().and_then(|ms| ms.parse::<i64>().ok());
+			let msg = CoreMsg::new(raw_msg).with_timestamp(timestamp).with_ack(Some(ack));
+			s.send(msg).await?;
+		}
+		Ok(())
+	}
+
+	// pending entries of the consumer first, they are not acked before restart or failed
+	async fn consume(
+		&self,
+		s: mpsc::Sender<CoreMsg>,
+		acks: mpsc::UnboundedSender<Acked>,
+	) -> anyhow::Result<()> {
+		let mut conn = self.connect().await?;
+		let created: redis::RedisResult<()> =
+			conn.xgroup_create_mkstream(&self.arg.stream, &self.arg.group, &self.arg.start).await;
+		match created {
+			Err(err) if err.code() != Some("BUSYGROUP") => {
+				return Err(err).context(format!("create group {}", self.arg.group));
+			}
+			_ => {}
+		}
+
+		let mut pending = Some("0".to_owned());
+		loop {
+			if pending.is_none() && self.retry.swap(false, Ordering::Relaxed) {
+				tokio::time::sleep(RETRY_DELAY).await;
+				pending = Some("0".to_owned());
+			}
+			let id = pending.as_deref().unwrap_or(">");
+			let entries = match self.read(&mut conn, id).await {
+				Ok(entries) => entries,
+				Err(err) => {
+					error!("redis stream {} read error {:?}", self.arg.stream, err);
+					tokio::time::sleep(Duration::from_secs(1)).await;
+					continue;
+				}
+			};
+			if pending.is_some() {
+				debug!("redis stream {} pending entries {}", self.arg.stream, entries.len());
+				pending = entries.last().map(|entry| entry.id.clone());
+			}
+			self.deliver(entries, &s, &acks).await?;
+		}
+	}
+
+	// XACK entries sunk or rejected, failed entries stay pending and are read again
+	async fn ack(&self, mut r: mpsc::UnboundedReceiver<Acked>) -> anyhow::Result<()> {
+		let mut conn = self.connect().await?;
+		let mut acked = Vec::with_capacity(ACK_BATCH);
+		while r.recv_many(&mut acked, ACK_BATCH).await > 0 {
+			let total = acked.len();
+			let (ids, failed) = settled(&mut acked);
+			if failed {
+				warn!(
+					"redis stream {} entries {} failed, read pending again",
+					self.arg.stream,
+					total - ids.len()
+				);
+				self.retry.store(true, Ordering::Relaxed);
+			}
+			if ids.is_empty() {
+				continue;
+			}
+			let res: redis::RedisResult<i64> =
+				conn.xack(&self.arg.stream, &self.arg.group, &ids).await;
+			if let Err(err) = res {
+				// read again as pending entries after restart or failed entries
+				error!("redis stream {} ack {} error {:?}", self.arg.stream, ids.len(), err);
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Source for RedisStreamSource {
+	#[instrument(skip(self, s))]
+	async fn source(&self, s: mpsc::Sender<CoreMsg>) -> anyhow::Result<()> {
+		info!(
+			"start redis stream {} group {} consumer {}",
+			self.arg.stream, self.arg.group, self.arg.consumer
+		);
+		let (acks, r) = mpsc::unbounded_channel();
+		tokio::try_join!(self.consume(s, acks), self.ack(r))?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashMap;
+
+	use redis::streams::StreamId;
+
+	use serde_json::json;
+
+	use super::settled;
+	use super::RedisStreamSource;
+	use crate::core::AckKind;
+	use crate::core::Acked;
+
+	fn entry(fields: &[(&str, &str)]) -> StreamId {
+		let map = fields
+			.iter()
+			.map(|(k, v)| (k.to_string(), redis::Value::Data(v.as_bytes().to_vec())))
+			.collect::<HashMap<_, _>>();
+		StreamId { id: "1719792000000-0".to_owned(), map }
+	}
+
+	#[test]
+	fn test_raw_msg() -> anyhow::Result<()> {
+		let arg = json!({"url": "redis://127.0.0.1:6379", "stream": "s", "group": "g"});
+		let source = RedisStreamSource::new(&arg)?;
+		let msg = source.raw_msg(&entry(&[("a", "1")])).unwrap();
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&msg)?, json!({"a": "1"}));
+
+		let mut arg = arg;
+		arg["field"] = json!("data");
+		let source = RedisStreamSource::new(&arg)?;
+		assert_eq!(source.raw_msg(&entry(&[("data", r#"{"a":1}"#)])).unwrap(), r#"{"a":1}"#);
+		assert_eq!(source.raw_msg(&entry(&[("a", "1")])), None);
+		Ok(())
+	}
+
+	#[test]
+	fn test_redis_settled() {
+		let acked = |id: &str, kind| Acked { id: id.to_owned(), kind };
+		// rejected entry is acked too, or it stays pending forever
+		let mut batch = vec![acked("1-0", AckKind::Done), acked("2-0", AckKind::Reject)];
+		assert_eq!(settled(&mut batch), (vec!["1-0".to_owned(), "2-0".to_owned()], false));
+		assert!(batch.is_empty());
+
+		let mut batch = vec![acked("3-0", AckKind::Retry), acked("4-0", AckKind::Done)];
+		assert_eq!(settled(&mut batch), (vec!["4-0".to_owned()], true));
+	}
+}
@@ -1,4 +1,8 @@
+pub mod aggregate;
 pub mod connector;
+pub mod dedup;
 pub mod job;
 pub mod link;
 pub mod task_manger;
+pub mod throttle;
+pub mod transform;
@@ -0,0 +1,177 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use lepumk::ani::Row;
+
+use crate::util::row_digest;
+
+// max messages per second from source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+	// messages per second
+	pub rate: f64,
+	// max messages pass at once, default one second of rate
+	#[serde(default)]
+	pub burst: Option<f64>,
+}
+
+// token bucket, only handle_msg take from it
+pub struct RateLimiter {
+	rate: f64,
+	burst: f64,
+	// tokens left and last refill
+	state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+	pub fn new(conf: &RateLimitConfig) -> anyhow::Result<Self> {
+		if !(conf.rate.is_finite() && conf.rate > 0.0) {
+			anyhow::bail!("rate limit rate must larger than 0");
+		}
+		let burst = conf.burst.unwrap_or(conf.rate).max(1.0);
+		Ok(Self { rate: conf.rate, burst, state: Mutex::new((burst, Instant::now())) })
+	}
+
+	// take one token, return wait time when bucket is empty
+	fn take(&self, now: Instant) -> Option<Duration> {
+		let mut state = self.state.lock().unwrap();
+		let (tokens, last) = &mut *state;
+		*tokens = (*tokens + now.saturating_duration_since(*last).as_secs_f64() * self.rate)
+			.min(self.burst);
+		*last = now;
+		*tokens -= 1.0;
+		// negative tokens is owed, later message wait longer
+		(*tokens < 0.0).then(|| Duration::from_secs_f64(-*tokens / self.rate))
+	}
+
+	// wait until a token, return true if throttled
+	pub async fn acquire(&self) -> bool {
+		match self.take(Instant::now()) {
+			Some(wait) => {
+				tokio::time::sleep(wait).await;
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+// keep rows by key hash, same key always keep or drop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleConfig {
+	// keep ratio in [0, 1]
+	pub ratio: f64,
+	// key fields, empty use the whole row
+	#[serde(default)]
+	pub keys: Vec<String>,
+}
+
+pub struct Sampler {
+	conf: SampleConfig,
+	threshold: u64,
+	// fields not in whole row hash like injected ingest time
+	skip: Vec<String>,
+}
+
+impl Sampler {
+	pub fn new(conf: &SampleConfig) -> anyhow::Result<Self> {
+		if !(0.0..=1.0).contains(&conf.ratio) {
+			anyhow::bail!("sample ratio must in [0, 1]");
+		}
+		let threshold = (conf.ratio * u64::MAX as f64) as u64;
+		Ok(Self { conf: conf.clone(), threshold, skip: vec![] })
+	}
+
+	pub fn with_skip(self, skip: Vec<String>) -> Self {
+		Self { skip, ..self }
+	}
+
+	fn hash(&self, row: &Row) -> u64 {
+		let digest = row_digest(row, &self.conf.keys, &self.skip);
+		let mut buf = [0u8; 8];
+		buf.copy_from_slice(&digest[..8]);
+		u64::from_be_bytes(buf)
+	}
+
+	// drop rows out of sample, return dropped count
+	pub fn filter(&self, rows: &mut Vec<Row>) -> i64 {
+		if self.conf.ratio >= 1.0 {
+			return 0;
+		}
+		let before = rows.len();
+		rows.retain(|row| self.conf.ratio > 0.0 && self.hash(row) <= self.threshold);
+		(before - rows.len()) as i64
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::time::Duration;
+	use std::time::Instant;
+
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::RateLimitConfig;
+	use super::RateLimiter;
+	use super::SampleConfig;
+	use super::Sampler;
+
+	#[test]
+	fn test_rate_limit() {
+		let conf: RateLimitConfig =
+			serde_json::from_value(json!({"rate": 10, "burst": 2})).unwrap();
+		let limiter = RateLimiter::new(&conf).unwrap();
+		let now = Instant::now();
+		assert_eq!(limiter.take(now), None);
+		assert_eq!(limiter.take(now), None);
+		assert_eq!(limiter.take(now), Some(Duration::from_millis(100)));
+		// refill one token after 100ms pay the owed
+		assert_eq!(limiter.take(now + Duration::from_millis(200)), None);
+
+		assert!(RateLimiter::new(&RateLimitConfig { rate: 0.0, burst: None }).is_err());
+	}
+
+	#[test]
+	fn test_sample() {
+		let sample = |conf: serde_json::Value| {
+			Sampler::new(&serde_json::from_value::<SampleConfig>(conf).unwrap()).unwrap()
+		};
+		let rows = (0..1000).map(|i| json!({"id": i % 100, "v": i})).collect::<Vec<_>>();
+		let rows: Vec<Row> = serde_json::from_value(json!(rows)).unwrap();
+
+		let s = sample(json!({"ratio": 0.3, "keys": ["id"]}));
+		let mut kept = rows.clone();
+		let dropped = s.filter(&mut kept);
+		assert_eq!(dropped as usize + kept.len(), 1000);
+		// key always keep or drop, ten rows per id
+		assert_eq!(kept.len() % 10, 0);
+		assert!((100..=500).contains(&kept.len()), "kept {}", kept.len());
+		let mut again = rows.clone();
+		s.filter(&mut again);
+		assert_eq!(kept, again);
+
+		let mut all = rows.clone();
+		assert_eq!(sample(json!({"ratio": 1})).filter(&mut all), 0);
+		assert_eq!(sample(json!({"ratio": 0})).filter(&mut all), 1000);
+		assert!(Sampler::new(&SampleConfig { ratio: 1.5, keys: vec![] }).is_err());
+
+		// redelivered row with a new ingest time is kept or dropped again
+		let s = sample(json!({"ratio": 0.5})).with_skip(vec!["ingest_at".to_owned()]);
+		let rows = (0..100).map(|i| json!({"id": i, "ingest_at": i})).collect::<Vec<_>>();
+		let mut first: Vec<Row> = serde_json::from_value(json!(rows)).unwrap();
+		let mut again = first.clone();
+		again.iter_mut().for_each(|row| row["ingest_at"] = json!(-1));
+		s.filter(&mut first);
+		s.filter(&mut again);
+		assert_eq!(
+			first.iter().map(|row| &row["id"]).collect::<Vec<_>>(),
+			again.iter().map(|row| &row["id"]).collect::<Vec<_>>()
+		);
+	}
+}
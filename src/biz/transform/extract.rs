@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+
+use regex::Captures;
+use regex::Regex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use lepumk::ani::schema::OnError;
+use lepumk::ani::Row;
+
+use super::MsgMeta;
+use super::Transform;
+
+// built in grok patterns, pattern can use the other by %{NAME}
+const GROK_PATTERNS: &[(&str, &str)] = &[
+	("WORD", r"\b\w+\b"),
+	("NOTSPACE", r"\S+"),
+	("SPACE", r"\s*"),
+	("DATA", r".*?"),
+	("GREEDYDATA", r".*"),
+	("INT", r"[+-]?[0-9]+"),
+	("POSINT", r"\b[1-9][0-9]*\b"),
+	("NUMBER", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+	(
+		"IPV4",
+		r"(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)",
+	),
+	("IPV6", r"(?:[0-9A-Fa-f]{0,4}:){2,7}[0-9A-Fa-f]{0,4}"),
+	("IP", r"(?:%{IPV4}|%{IPV6})"),
+	("HOSTNAME", r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b"),
+	("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+	("UUID", r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}"),
+	("EMAILADDRESS", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+"),
+	(
+		"LOGLEVEL",
+		r"(?i:trace|debug|info|notice|warn(?:ing)?|err(?:or)?|crit(?:ical)?|fatal|severe|emerg(?:ency)?)",
+	),
+	(
+		"TIMESTAMP_ISO8601",
+		r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:[.,]\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?",
+	),
+	("HTTPDATE", r"\d{2}/\w{3}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4}"),
+	("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*""#),
+	("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+];
+
+// nest pattern max depth
+const GROK_MAX_DEPTH: usize = 16;
+
+// extract columns from string value of flattened field
+// use named capture regex or grok, field or grok must set one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractRule {
+	// flattened field like message or data_message
+	pub field: String,
+	// regex with named capture like (?P<level>\w+)
+	#[serde(default)]
+	pub regex: Option<String>,
+	// grok like %{IPV4:client} %{INT:status:int}
+	#[serde(default)]
+	pub grok: Option<String>,
+	// user define grok patterns
+	#[serde(default)]
+	pub patterns: HashMap<String, String>,
+	// field missing or not match
+	#[serde(default)]
+	pub on_error: OnError,
+	// column value with on_error default
+	#[serde(default)]
+	pub default: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaptureType {
+	String,
+	Int,
+	Float,
+}
+
+impl CaptureType {
+	fn parse(s: &str) -> anyhow::Result<Self> {
+		match s {
+			"string" => Ok(CaptureType::String),
+			"int" => Ok(CaptureType::Int),
+			"float" => Ok(CaptureType::Float),
+			other => anyhow::bail!("unknown grok type {}", other),
+		}
+	}
+
+	fn covert(self, s: &str) -> Option<serde_json::Value> {
+		match self {
+			CaptureType::String => Some(serde_json::Value::String(s.to_owned())),
+			CaptureType::Int => s.parse::<i64>().ok().map(serde_json::Value::from),
+			CaptureType::Float => s
+				.parse::<f64>()
+				.ok()
+				.and_then(serde_json::Number::from_f64)
+				.map(serde_json::Value::Number),
+		}
+	}
+}
+
+// compiled rule
+struct Extraction {
+	field: String,
+	re: Regex,
+	// capture group name, output column and type
+	columns: Vec<(String, String, CaptureType)>,
+	on_error: OnError,
+	default: HashMap<String, serde_json::Value>,
+}
+
+pub struct Extractor {
+	rules: Vec<Extraction>,
+}
+
+impl Extractor {
+	// compile every rule once
+	pub fn new(rules: &[ExtractRule]) -> anyhow::Result<Self> {
+		let rules = rules.iter().map(Extraction::new).collect::<anyhow::Result<Vec<_>>>()?;
+		Ok(Self { rules })
+	}
+}
+
+impl Extraction {
+	fn new(rule: &ExtractRule) -> anyhow::Result<Self> {
+		let (re, columns) = match (&rule.regex, &rule.grok) {
+			(Some(re), None) => {
+				let re = Regex::new(re)
+					.map_err(|err| anyhow::anyhow!("extract regex {} error {}", re, err))?;
+				let columns = re
+					.capture_names()
+					.flatten()
+					.map(|name| (name.to_owned(), name.to_owned(), CaptureType::String))
+					.collect();
+				(re, columns)
+			}
+			(None, Some(grok)) => {
+				let mut columns = vec![];
+				let re = grok_to_regex(grok, &rule.patterns, &mut columns, 0)?;
+				let re = Regex::new(&re)
+					.map_err(|err| anyhow::anyhow!("extract grok {} error {}", grok, err))?;
+				(re, columns)
+			}
+			_ => anyhow::bail!("extract field {} must set one of regex or grok", rule.field),
+		};
+
+		if columns.is_empty() {
+			anyhow::bail!("extract field {} has no named capture", rule.field);
+		}
+
+		Ok(Self {
+			field: rule.field.clone(),
+			re,
+			columns,
+			on_error: rule.on_error,
+			default: rule.default.clone(),
+		})
+	}
+
+	// extract columns, none if field missing or not match
+	fn extract(&self, row: &Row) -> Option<Vec<(String, serde_json::Value)>> {
+		let text = match row.get(&self.field)? {
+			serde_json::Value::Null => return None,
+			serde_json::Value::String(s) => std::borrow::Cow::Borrowed(s.as_str()),
+			other => std::borrow::Cow::Owned(other.to_string()),
+		};
+		let caps = self.re.captures(&text)?;
+		self.columns
+			.iter()
+			.map(|(group, column, kind)| self.column(&caps, group, column, *kind))
+			.collect()
+	}
+
+	// group not participate in the match is null
+	fn column(
+		&self,
+		caps: &Captures,
+		group: &str,
+		column: &str,
+		kind: CaptureType,
+	) -> Option<(String, serde_json::Value)> {
+		let val = match caps.name(group) {
+			Some(m) => kind.covert(m.as_str())?,
+			None => serde_json::Value::Null,
+		};
+		Some((column.to_owned(), val))
+	}
+
+	fn fallback(&self) -> Vec<(String, serde_json::Value)> {
+		self.columns
+			.iter()
+			.map(|(_, column, _)| {
+				let val = match self.on_error {
+					OnError::Default => {
+						self.default.get(column).cloned().unwrap_or(serde_json::Value::Null)
+					}
+					_ => serde_json::Value::Null,
+				};
+				(column.clone(), val)
+			})
+			.collect()
+	}
+}
+
+impl Transform for Extractor {
+	fn apply(&self, rows: &mut Vec<Row>, _meta: &MsgMeta) -> anyhow::Result<()> {
+		let mut res = Vec::with_capacity(rows.len());
+		'row: for mut row in rows.drain(..) {
+			for rule in self.rules.iter() {
+				let columns = match rule.extract(&row) {
+					Some(columns) => columns,
+					None => match rule.on_error {
+						OnError::Null | OnError::Default => rule.fallback(),
+						OnError::DropRow => continue 'row,
+						OnError::Fail => {
+							anyhow::bail!(
+								"extract field {} value {:?} not match {}",
+								rule.field,
+								row.get(&rule.field),
+								rule.re
+							)
+						}
+					},
+				};
+				row.extend(columns);
+			}
+			res.push(row);
+		}
+		*rows = res;
+		Ok(())
+	}
+}
+
+// expand grok into regex, %{NAME:column:type} as capture group
+fn grok_to_regex(
+	grok: &str,
+	patterns: &HashMap<String, String>,
+	columns: &mut Vec<(String, String, CaptureType)>,
+	depth: usize,
+) -> anyhow::Result<String> {
+	lazy_static::lazy_static! {
+		static ref GROK: Regex = Regex::new(r"%\{(\w+)(?::([^:}]+))?(?::(\w+))?\}").unwrap();
+	}
+
+	if depth > GROK_MAX_DEPTH {
+		anyhow::bail!("grok pattern nest too deep {}", grok);
+	}
+
+	let mut res = String::with_capacity(grok.len());
+	let mut last = 0;
+	for caps in GROK.captures_iter(grok) {
+		let all = caps.get(0).unwrap();
+		res.push_str(&grok[last..all.start()]);
+		last = all.end();
+
+		let name = &caps[1];
+		let pattern = match patterns.get(name) {
+			Some(pattern) => pattern.as_str(),
+			None => match GROK_PATTERNS.iter().find(|(k, _)| *k == name) {
+				Some((_, pattern)) => *pattern,
+				None => anyhow::bail!("unknown grok pattern {}", name),
+			},
+		};
+		// nested pattern never capture
+		let inner = grok_to_regex(pattern, patterns, &mut vec![], depth + 1)?;
+
+		match caps.get(2) {
+			Some(column) => {
+				let kind = match caps.get(3) {
+					Some(kind) => CaptureType::parse(kind.as_str())?,
+					None => CaptureType::String,
+				};
+				// column like a.b is not valid group name
+				let group = format!("g{}", columns.len());
+				res.push_str(&format!("(?P<{group}>{inner})"));
+				columns.push((group, column.as_str().to_owned(), kind));
+			}
+			None => res.push_str(&format!("(?:{inner})")),
+		}
+	}
+	res.push_str(&grok[last..]);
+	Ok(res)
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::ExtractRule;
+	use super::Extractor;
+	use crate::biz::transform::MsgMeta;
+	use crate::biz::transform::Transform;
+
+	fn extract(rules: serde_json::Value, rows: serde_json::Value) -> anyhow::Result<Vec<Row>> {
+		let rules: Vec<ExtractRule> = serde_json::from_value(rules)?;
+		let mut rows: Vec<Row> = serde_json::from_value(rows)?;
+		Extractor::new(&rules)?.apply(&mut rows, &MsgMeta::new(None))?;
+		Ok(rows)
+	}
+
+	#[test]
+	fn test_extract_grok() -> anyhow::Result<()> {
+		let rows = extract(
+			json!([{
+				"field": "message",
+				"grok": "%{IPORHOST:client.ip} %{WORD:method} %{URIPATH:path} %{INT:status:int} %{NUMBER:cost:float}"
+			}]),
+			json!([{"message": "10.0.0.1 GET /a/b 200 0.5", "id": 1}]),
+		)?;
+		assert_eq!(
+			json!(rows),
+			json!([{
+				"message": "10.0.0.1 GET /a/b 200 0.5",
+				"id": 1,
+				"client.ip": "10.0.0.1",
+				"method": "GET",
+				"path": "/a/b",
+				"status": 200,
+				"cost": 0.5
+			}])
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_extract_regex() -> anyhow::Result<()> {
+		let rule = |on_error: &str| {
+			json!([{
+				"field": "message",
+				"regex": r"^\[(?P<level>\w+)\] (?P<text>.*)$",
+				"on_error": on_error,
+				"default": {"level": "unknown"}
+			}])
+		};
+		let rows = || json!([{"message": "[warn] disk full"}, {"message": "oops"}, {"other": 1}]);
+
+		let res = extract(rule("null"), rows())?;
+		assert_eq!(res[0]["level"], json!("warn"));
+		assert_eq!(res[0]["text"], json!("disk full"));
+		assert!(res[1]["level"].is_null());
+		assert!(res[2]["text"].is_null());
+
+		let res = extract(rule("default"), rows())?;
+		assert_eq!(res[1]["level"], json!("unknown"));
+
+		let res = extract(rule("drop_row"), rows())?;
+		assert_eq!(res.len(), 1);
+
+		assert!(extract(rule("fail"), rows()).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_extract_config() {
+		let rules = |rule: serde_json::Value| {
+			let rules: Vec<ExtractRule> = serde_json::from_value(json!([rule])).unwrap();
+			Extractor::new(&rules)
+		};
+		assert!(rules(json!({"field": "a", "grok": "%{NOPE:x}"})).is_err());
+		assert!(rules(json!({"field": "a", "regex": "(\\w+)"})).is_err());
+		assert!(rules(json!({"field": "a"})).is_err());
+		assert!(rules(json!({"field": "a", "grok": "%{A:x}", "patterns": {"A": "%{A}"}})).is_err());
+		assert!(rules(
+			json!({"field": "a", "grok": "%{ID:x}", "patterns": {"ID": "%{INT}-%{WORD}"}})
+		)
+		.is_ok());
+	}
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::Weak;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use lazy_static::lazy_static;
+
+use regex::Regex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sqlx::MySqlPool;
+use sqlx::Row as _;
+
+use tracing::error;
+use tracing::info;
+
+use lepumk::ani::Row;
+
+use super::MsgMeta;
+use super::Transform;
+
+lazy_static! {
+	// table or db.table
+	static ref IDENT: Regex =
+		Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*(\.[A-Za-z_][A-Za-z0-9_]*)?$").unwrap();
+}
+
+// add columns of reference table keyed by a row field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupConfig {
+	// row field look up with
+	pub field: String,
+	pub source: LookupSource,
+	// key column of reference table
+	pub key: String,
+	// columns added, empty is all columns except key
+	#[serde(default)]
+	pub columns: Vec<String>,
+	// prefix of added columns
+	#[serde(default)]
+	pub prefix: String,
+	// seconds reload the table, 0 is load once
+	#[serde(default)]
+	pub refresh: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LookupSource {
+	// csv with header, value is string
+	Csv { path: String },
+	// json object per line
+	Ndjson { path: String },
+	// mysql table of app database, value is string
+	Mysql { table: String },
+	// rows in config, ready without load
+	Inline { rows: Vec<Row> },
+}
+
+impl LookupSource {
+	// read nothing out of the config
+	pub fn is_inline(&self) -> bool {
+		matches!(self, LookupSource::Inline { .. })
+	}
+}
+
+// key string of json value, string without quote
+fn key_of(val: &serde_json::Value) -> Option<String> {
+	match val {
+		serde_json::Value::Null => None,
+		serde_json::Value::String(s) => Some(s.clone()),
+		other => Some(other.to_string()),
+	}
+}
+
+type Table = HashMap<String, Row>;
+
+pub struct Lookup {
+	conf: LookupConfig,
+	table: Arc<RwLock<Table>>,
+}
+
+impl Lookup {
+	pub fn new(conf: &LookupConfig) -> anyhow::Result<Self> {
+		if let LookupSource::Mysql { table } = &conf.source {
+			for ident in
+				std::iter::once(table).chain(std::iter::once(&conf.key)).chain(&conf.columns)
+			{
+				if !IDENT.is_match(ident) {
+					anyhow::bail!("lookup mysql identifier {} invalid", ident);
+				}
+			}
+		}
+		let table = match &conf.source {
+			LookupSource::Inline { rows } => index(conf, rows.clone()),
+			_ => Table::default(),
+		};
+		Ok(Self { conf: conf.clone(), table: Arc::new(RwLock::new(table)) })
+	}
+
+	// load table, the transform miss every row before load
+	pub async fn load(&self, conn: &MySqlPool) -> anyhow::Result<()> {
+		let table = Self::fetch(&self.conf, conn).await?;
+		info!("load lookup {:?} size {}", self.conf.source, table.len());
+		*self.table.write().unwrap() = table;
+		Ok(())
+	}
+
+	// reload table every refresh seconds until the lookup drop
+	pub fn refresh(&self, conn: MySqlPool) {
+		if self.conf.refresh == 0 {
+			return;
+		}
+		let conf = self.conf.clone();
+		let weak: Weak<RwLock<Table>> = Arc::downgrade(&self.table);
+		tokio::task::spawn(async move {
+			let mut ticker = tokio::time::interval(Duration::from_secs(conf.refresh));
+			// first tick is immediately, table is loaded
+			ticker.tick().await;
+			loop {
+				ticker.tick().await;
+				if weak.strong_count() == 0 {
+					break;
+				}
+				// keep the old table when reload error
+				let table = match Self::fetch(&conf, &conn).await {
+					Ok(table) => table,
+					Err(err) => {
+						error!("refresh lookup {:?} error {:?}", conf.source, err);
+						continue;
+					}
+				};
+				let Some(lock) = weak.upgrade() else {
+					break;
+				};
+				*lock.write().unwrap() = table;
+			}
+			info!("stop refresh lookup {:?}", conf.source);
+		});
+	}
+
+	async fn fetch(conf: &LookupConfig, conn: &MySqlPool) -> anyhow::Result<Table> {
+		let rows = match &conf.source {
+			LookupSource::Csv { path } => {
+				let content = tokio::fs::read(path)
+					.await
+					.with_context(|| format!("read lookup csv {}", path))?;
+				parse_csv(&content).with_context(|| format!("decode lookup csv {}", path))?
+			}
+			LookupSource::Ndjson { path } => {
+				let content = tokio::fs::read_to_string(path)
+					.await
+					.with_context(|| format!("read lookup ndjson {}", path))?;
+				parse_ndjson(&content).with_context(|| format!("decode lookup ndjson {}", path))?
+			}
+			LookupSource::Mysql { table } => fetch_mysql(conf, table, conn).await?,
+			LookupSource::Inline { rows } => rows.clone(),
+		};
+		Ok(index(conf, rows))
+	}
+}
+
+// rows by key, with the configured columns
+fn index(conf: &LookupConfig, rows: Vec<Row>) -> Table {
+	let mut res = HashMap::with_capacity(rows.len());
+	for mut row in rows {
+		let Some(key) = row.get(&conf.key).and_then(key_of) else {
+			continue;
+		};
+		if conf.columns.is_empty() {
+			row.shift_remove(&conf.key);
+		} else {
+			row.retain(|k, _| conf.columns.contains(k));
+		}
+		// first row of the key win
+		res.entry(key).or_insert(row);
+	}
+	res
+}
+
+fn parse_csv(content: &[u8]) -> anyhow::Result<Vec<Row>> {
+	let mut reader = csv::Reader::from_reader(content);
+	let headers = reader.headers()?.clone();
+	let mut rows = vec![];
+	for record in reader.records() {
+		let record = record?;
+		let row = headers
+			.iter()
+			.zip(record.iter())
+			.map(|(k, v)| (k.to_owned(), serde_json::Value::String(v.to_owned())))
+			.collect::<Row>();
+		rows.push(row);
+	}
+	Ok(rows)
+}
+
+fn parse_ndjson(content: &str) -> anyhow::Result<Vec<Row>> {
+	content
+		.lines()
+		.filter(|line| !line.trim().is_empty())
+		.enumerate()
+		.map(|(i, line)| serde_json::from_str(line).with_context(|| format!("line {}", i + 1)))
+		.collect()
+}
+
+// select columns as char, null keep null
+async fn fetch_mysql(
+	conf: &LookupConfig,
+	table: &str,
+	conn: &MySqlPool,
+) -> anyhow::Result<Vec<Row>> {
+	let quote = |ident: &str| ident.split('.').map(|s| format!("`{}`", s)).collect::<Vec<_>>();
+	let mut columns = conf.columns.clone();
+	if columns.is_empty() {
+		let sql = format!("SHOW COLUMNS FROM {}", quote(table).join("."));
+		let rows = sqlx::query(&sql).fetch_all(conn).await?;
+		columns = rows
+			.iter()
+			.map(|row| {
+				let field: Vec<u8> = row.try_get("Field")?;
+				Ok(String::from_utf8_lossy(&field).into_owned())
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
+	}
+	if !columns.contains(&conf.key) {
+		columns.push(conf.key.clone());
+	}
+
+	let select = columns
+		.iter()
+		.map(|column| format!("CAST(`{}` AS CHAR)", column))
+		.collect::<Vec<_>>()
+		.join(", ");
+	let sql = format!("SELECT {} FROM {}", select, quote(table).join("."));
+	let rows = sqlx::query(&sql)
+		.fetch_all(conn)
+		.await
+		.with_context(|| format!("fetch lookup table {}", table))?;
+
+	rows.iter()
+		.map(|row| {
+			let mut res = Row::with_capacity(columns.len());
+			for (i, column) in columns.iter().enumerate() {
+				let val: Option<String> = row.try_get(i)?;
+				res.insert(column.clone(), val.map_or(serde_json::Value::Null, Into::into));
+			}
+			Ok(res)
+		})
+		.collect()
+}
+
+impl Transform for Lookup {
+	// miss add null of configured columns, so the output keys are stable
+	fn apply(&self, rows: &mut Vec<Row>, _meta: &MsgMeta) -> anyhow::Result<()> {
+		let table = self.table.read().unwrap();
+		for row in rows.iter_mut() {
+			let found = row.get(&self.conf.field).and_then(key_of).and_then(|key| table.get(&key));
+			match found {
+				Some(found) => {
+					for (k, v) in found.iter() {
+						row.insert(format!("{}{}", self.conf.prefix, k), v.clone());
+					}
+				}
+				None => {
+					for column in self.conf.columns.iter() {
+						row.insert(
+							format!("{}{}", self.conf.prefix, column),
+							serde_json::Value::Null,
+						);
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use sqlx::MySqlPool;
+
+	use lepumk::ani::Row;
+
+	use super::Lookup;
+	use super::LookupConfig;
+	use crate::biz::transform::MsgMeta;
+	use crate::biz::transform::Transform;
+
+	fn lookup(conf: serde_json::Value) -> anyhow::Result<Lookup> {
+		let conf: LookupConfig = serde_json::from_value(conf)?;
+		Lookup::new(&conf)
+	}
+
+	fn apply(l: &Lookup, rows: serde_json::Value) -> anyhow::Result<Vec<Row>> {
+		let mut rows: Vec<Row> = serde_json::from_value(rows)?;
+		l.apply(&mut rows, &MsgMeta::new(None))?;
+		Ok(rows)
+	}
+
+	#[tokio::test]
+	async fn test_lookup_file() -> anyhow::Result<()> {
+		// never connect, file source only
+		let conn = MySqlPool::connect_lazy("mysql://root@127.0.0.1/test")?;
+		let dir = std::env::temp_dir();
+		let csv = dir.join(format!("lookup_{}.csv", std::process::id()));
+		std::fs::write(
+			&csv,
+			"code,name,region\nCN,China,\"Asia, East\"\nUS,United States,America\n",
+		)?;
+		let ndjson = dir.join(format!("lookup_{}.ndjson", std::process::id()));
+		std::fs::write(
+			&ndjson,
+			"{\"id\": 1, \"title\": \"book\", \"price\": 9.5}\n\n{\"id\": 2}\n",
+		)?;
+
+		let l = lookup(json!({
+			"field": "country",
+			"source": {"type": "csv", "path": csv.to_str().unwrap()},
+			"key": "code",
+			"columns": ["name", "region"],
+			"prefix": "country_"
+		}))?;
+		// miss before load
+		let rows = apply(&l, json!([{"country": "CN"}]))?;
+		assert!(rows[0]["country_name"].is_null());
+
+		l.load(&conn).await?;
+		let rows = apply(&l, json!([{"country": "CN"}, {"country": "JP"}, {}]))?;
+		assert_eq!(rows[0]["country_name"], json!("China"));
+		assert_eq!(rows[0]["country_region"], json!("Asia, East"));
+		assert!(rows[1]["country_name"].is_null());
+		assert!(rows[2].contains_key("country_region"));
+
+		let l = lookup(json!({
+			"field": "product_id",
+			"source": {"type": "ndjson", "path": ndjson.to_str().unwrap()},
+			"key": "id"
+		}))?;
+		l.load(&conn).await?;
+		let rows = apply(&l, json!([{"product_id": "1"}, {"product_id": 3}]))?;
+		assert_eq!(rows[0]["title"], json!("book"));
+		assert_eq!(rows[0]["price"], json!(9.5));
+		assert!(!rows[0].contains_key("id"));
+		assert_eq!(rows[1].len(), 1);
+
+		let _ = std::fs::remove_file(csv);
+		let _ = std::fs::remove_file(ndjson);
+		Ok(())
+	}
+
+	#[test]
+	fn test_lookup_config() {
+		let mysql = |table: &str, key: &str| {
+			lookup(json!({"field": "f", "source": {"type": "mysql", "table": table}, "key": key}))
+		};
+		assert!(mysql("db.country", "code").is_ok());
+		assert!(mysql("country; drop table x", "code").is_err());
+		assert!(mysql("country", "code`").is_err());
+	}
+
+	#[test]
+	fn test_lookup_inline() -> anyhow::Result<()> {
+		let l = lookup(json!({
+			"field": "country",
+			"source": {"type": "inline", "rows": [{"code": "CN", "name": "China"}]},
+			"key": "code"
+		}))?;
+		// ready without load
+		let rows = apply(&l, json!([{"country": "CN"}]))?;
+		assert_eq!(rows[0]["name"], json!("China"));
+		Ok(())
+	}
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use hmac::Hmac;
+use hmac::Mac;
+
+use regex::Regex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sha2::Sha256;
+
+use lepumk::ani::Row;
+
+use crate::conf::MaskConf;
+
+use super::MsgMeta;
+use super::Transform;
+
+// hash and tokenize secret, load from app config only
+static SECRET: OnceLock<String> = OnceLock::new();
+
+pub fn init_secret(conf: &MaskConf) {
+	let _ = SECRET.set(conf.secret.clone());
+}
+
+pub fn secret() -> &'static str {
+	SECRET.get().map(String::as_str).unwrap_or_default()
+}
+
+// mask value of output key, key or pattern must set one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaskRule {
+	// output key
+	#[serde(default)]
+	pub key: Option<String>,
+	// regex of output key
+	#[serde(default)]
+	pub pattern: Option<String>,
+	#[serde(flatten)]
+	pub action: MaskAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MaskAction {
+	// replace the whole value
+	Redact {
+		#[serde(default = "default_replace")]
+		replace: String,
+	},
+	// keep head and tail chars, mask the others
+	Partial {
+		#[serde(default)]
+		keep_head: usize,
+		#[serde(default)]
+		keep_tail: usize,
+		#[serde(default = "default_mask_char")]
+		mask_char: char,
+	},
+	// hex of hmac-sha256 with secret
+	Hash,
+	// short stable token from hmac-sha256 with secret
+	Tokenize {
+		#[serde(default = "default_prefix")]
+		prefix: String,
+	},
+}
+
+fn default_replace() -> String {
+	"***".to_owned()
+}
+
+fn default_mask_char() -> char {
+	'*'
+}
+
+fn default_prefix() -> String {
+	"tok_".to_owned()
+}
+
+// hex chars of token
+const TOKEN_LEN: usize = 16;
+
+impl MaskAction {
+	fn need_secret(&self) -> bool {
+		matches!(self, MaskAction::Hash | MaskAction::Tokenize { .. })
+	}
+}
+
+pub struct Masker {
+	rules: Vec<MaskAction>,
+	// output key to rule index
+	keys: HashMap<String, usize>,
+	// key pattern to rule index
+	patterns: Vec<(Regex, usize)>,
+	// boxed, hmac state is large
+	mac: Box<Hmac<Sha256>>,
+}
+
+impl Masker {
+	pub fn new(rules: &[MaskRule], secret: &str) -> anyhow::Result<Self> {
+		let mut keys = HashMap::new();
+		let mut patterns = vec![];
+		for (i, rule) in rules.iter().enumerate() {
+			if rule.action.need_secret() && secret.is_empty() {
+				anyhow::bail!("mask {:?} need secret, set secret in [mask] of app config", rule);
+			}
+			match (&rule.key, &rule.pattern) {
+				(Some(key), None) => {
+					// first rule win
+					keys.entry(key.clone()).or_insert(i);
+				}
+				(None, Some(pattern)) => {
+					let re = Regex::new(pattern)
+						.map_err(|err| anyhow::anyhow!("mask pattern {} error {}", pattern, err))?;
+					patterns.push((re, i));
+				}
+				_ => anyhow::bail!("mask rule {:?} must set one of key or pattern", rule),
+			}
+		}
+
+		let mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+			.map_err(|err| anyhow::anyhow!("mask secret error {}", err))?;
+		let rules = rules.iter().map(|rule| rule.action.clone()).collect();
+		Ok(Self { rules, keys, patterns, mac: Box::new(mac) })
+	}
+
+	// rule of key, key match before pattern
+	fn rule(&self, key: &str) -> Option<&MaskAction> {
+		let i = match self.keys.get(key) {
+			Some(i) => Some(*i),
+			None => self.patterns.iter().find(|(re, _)| re.is_match(key)).map(|(_, i)| *i),
+		};
+		i.map(|i| &self.rules[i])
+	}
+
+	fn hmac(&self, s: &str) -> String {
+		let mut mac = (*self.mac).clone();
+		mac.update(s.as_bytes());
+		hex::encode(mac.finalize().into_bytes())
+	}
+
+	fn mask(&self, action: &MaskAction, s: &str) -> String {
+		match action {
+			MaskAction::Redact { replace } => replace.clone(),
+			MaskAction::Partial { keep_head, keep_tail, mask_char } => {
+				let len = s.chars().count();
+				if keep_head + keep_tail >= len {
+					return std::iter::repeat(*mask_char).take(len).collect();
+				}
+				let keep = |i: usize| i < *keep_head || i + keep_tail >= len;
+				s.chars().enumerate().map(|(i, c)| if keep(i) { c } else { *mask_char }).collect()
+			}
+			MaskAction::Hash => self.hmac(s),
+			MaskAction::Tokenize { prefix } => {
+				let mut token = self.hmac(s);
+				token.truncate(TOKEN_LEN);
+				format!("{prefix}{token}")
+			}
+		}
+	}
+}
+
+impl Transform for Masker {
+	// null keep null, other value mask as string
+	fn apply(&self, rows: &mut Vec<Row>, _meta: &MsgMeta) -> anyhow::Result<()> {
+		for row in rows.iter_mut() {
+			for (key, val) in row.iter_mut() {
+				let Some(action) = self.rule(key) else {
+					continue;
+				};
+				let masked = match &*val {
+					serde_json::Value::Null => continue,
+					serde_json::Value::String(s) => self.mask(action, s),
+					other => self.mask(action, &other.to_string()),
+				};
+				*val = serde_json::Value::String(masked);
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::MaskRule;
+	use super::Masker;
+	use crate::biz::transform::MsgMeta;
+	use crate::biz::transform::Transform;
+
+	fn masker(rules: serde_json::Value, secret: &str) -> anyhow::Result<Masker> {
+		let rules: Vec<MaskRule> = serde_json::from_value(rules)?;
+		Masker::new(&rules, secret)
+	}
+
+	#[test]
+	fn test_mask() -> anyhow::Result<()> {
+		let m = masker(
+			json!([
+				{"key": "password", "action": "redact"},
+				{"key": "phone", "action": "partial", "keep_head": 3, "keep_tail": 2},
+				{"key": "email", "action": "hash"},
+				{"pattern": "_id$", "action": "tokenize"},
+				{"pattern": "^phone", "action": "redact", "replace": ""}
+			]),
+			"secret",
+		)?;
+
+		let mut rows: Vec<Row> = vec![serde_json::from_value(json!({
+			"password": "abc",
+			"phone": 13812345678_i64,
+			"email": "a@b.c",
+			"user_id": "u1",
+			"phone_ext": null,
+			"name": "keep"
+		}))?];
+		m.apply(&mut rows, &MsgMeta::new(None))?;
+
+		let row = &rows[0];
+		assert_eq!(row["password"], json!("***"));
+		assert_eq!(row["phone"], json!("138******78"));
+		assert_eq!(row["email"].as_str().unwrap().len(), 64);
+		assert_eq!(row["user_id"].as_str().unwrap().len(), 20);
+		assert!(row["user_id"].as_str().unwrap().starts_with("tok_"));
+		assert!(row["phone_ext"].is_null());
+		assert_eq!(row["name"], json!("keep"));
+
+		// hash is stable with the same secret
+		let other = masker(json!([{"key": "email", "action": "hash"}]), "secret")?;
+		let mut again: Vec<Row> = vec![serde_json::from_value(json!({"email": "a@b.c"}))?];
+		other.apply(&mut again, &MsgMeta::new(None))?;
+		assert_eq!(row["email"], again[0]["email"]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_mask_config() {
+		assert!(masker(json!([{"key": "email", "action": "hash"}]), "").is_err());
+		assert!(masker(json!([{"action": "redact"}]), "").is_err());
+		assert!(masker(json!([{"pattern": "(", "action": "redact"}]), "").is_err());
+		assert!(masker(json!([{"key": "a", "action": "partial"}]), "").is_ok());
+	}
+}
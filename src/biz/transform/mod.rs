@@ -0,0 +1,150 @@
+use enum_dispatch::enum_dispatch;
+
+use lepumk::ani::JsonParser;
+use lepumk::ani::Parsed;
+use lepumk::ani::Row;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sqlx::MySqlPool;
+
+pub mod extract;
+pub mod lookup;
+pub mod mask;
+pub mod timestamp;
+
+use extract::*;
+use lookup::*;
+use mask::*;
+use timestamp::*;
+
+#[enum_dispatch]
+pub enum TransformEnum {
+	Extractor,
+	Lookup,
+	Timestamp,
+	Masker,
+}
+
+// change rows after json parser
+#[enum_dispatch(TransformEnum)]
+pub trait Transform {
+	fn apply(&self, rows: &mut Vec<Row>, meta: &MsgMeta) -> anyhow::Result<()>;
+}
+
+// message info beside the raw text
+#[derive(Debug, Clone, Copy)]
+pub struct MsgMeta {
+	// epoch millis the message handled
+	pub ingest_time: i64,
+	// epoch millis message timestamp from source like kafka
+	pub source_time: Option<i64>,
+}
+
+impl MsgMeta {
+	pub fn new(source_time: Option<i64>) -> Self {
+		Self { ingest_time: chrono::Utc::now().timestamp_millis(), source_time }
+	}
+}
+
+// json parser and then every transform in order
+// task and debug endpoint use the same pipeline
+pub struct Pipeline {
+	parser: JsonParser,
+	transforms: Vec<TransformEnum>,
+}
+
+impl Pipeline {
+	pub fn new(parser: JsonParser) -> Self {
+		Self { parser, transforms: vec![] }
+	}
+
+	pub fn with_transform(mut self, transform: impl Into<TransformEnum>) -> Self {
+		self.transforms.push(transform.into());
+		self
+	}
+
+	pub fn parser(&self) -> &JsonParser {
+		&self.parser
+	}
+}
+
+impl Pipeline {
+	// parser error is lepumk::ani::error::ParseError
+	pub fn run(&self, s: &str, meta: &MsgMeta) -> anyhow::Result<Parsed> {
+		let parsed = self.parser.parse_str(s)?;
+		self.transform(parsed, meta)
+	}
+
+	// run with parser trace
+	pub fn explain(&self, s: &str, meta: &MsgMeta) -> anyhow::Result<Parsed> {
+		let parsed = self.parser.explain_str(s)?;
+		self.transform(parsed, meta)
+	}
+
+	// load lookup tables, run miss the lookup before load
+	pub async fn load(&self, conn: &MySqlPool) -> anyhow::Result<()> {
+		for transform in self.transforms.iter() {
+			if let TransformEnum::Lookup(lookup) = transform {
+				lookup.load(conn).await?;
+			}
+		}
+		Ok(())
+	}
+
+	// reload lookup tables until the pipeline drop
+	pub fn refresh(&self, conn: &MySqlPool) {
+		for transform in self.transforms.iter() {
+			if let TransformEnum::Lookup(lookup) = transform {
+				lookup.refresh(conn.clone());
+			}
+		}
+	}
+
+	fn transform(&self, mut parsed: Parsed, meta: &MsgMeta) -> anyhow::Result<Parsed> {
+		for transform in self.transforms.iter() {
+			transform.apply(&mut parsed.rows, meta)?;
+		}
+		Ok(parsed)
+	}
+}
+
+// transform part of parser config
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TransformConfig {
+	// extract columns from string field
+	#[serde(default)]
+	pub extract: Vec<ExtractRule>,
+	// add columns from reference table
+	#[serde(default)]
+	pub lookup: Vec<LookupConfig>,
+	// normalize timestamp columns
+	#[serde(default)]
+	pub timestamp: Option<TimestampConfig>,
+	// mask rules of output key
+	#[serde(default)]
+	pub mask: Vec<MaskRule>,
+}
+
+impl TransformConfig {
+	// build pipeline, hash secret from app config
+	// order is extract, lookup, timestamp and then mask
+	// so extracted or looked up column can be normalized or masked
+	pub fn build(&self, parser: JsonParser) -> anyhow::Result<Pipeline> {
+		let mut pipeline = Pipeline::new(parser);
+		if !self.extract.is_empty() {
+			pipeline = pipeline.with_transform(Extractor::new(&self.extract)?);
+		}
+		for lookup in self.lookup.iter() {
+			pipeline = pipeline.with_transform(Lookup::new(lookup)?);
+		}
+		if let Some(timestamp) = &self.timestamp {
+			pipeline = pipeline.with_transform(Timestamp::new(timestamp)?);
+		}
+		if !self.mask.is_empty() {
+			pipeline = pipeline.with_transform(Masker::new(&self.mask, mask::secret())?);
+		}
+		Ok(pipeline)
+	}
+}
@@ -0,0 +1,323 @@
+use chrono::format::Item;
+use chrono::format::StrftimeItems;
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+use chrono::Utc;
+
+use chrono_tz::Tz;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use lepumk::ani::schema::to_timestamp;
+use lepumk::ani::schema::OnError;
+use lepumk::ani::Row;
+
+use super::MsgMeta;
+use super::Transform;
+
+// datetime without timezone tried when formats is empty
+const DETECT_FORMATS: &[&str] =
+	&["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M:%S%.f"];
+
+// normalize timestamp columns and inject ingest or source time
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TimestampConfig {
+	#[serde(default)]
+	pub columns: Vec<TimestampColumn>,
+	// output representation
+	#[serde(default)]
+	pub target: TimeTarget,
+	// strftime format with target format
+	#[serde(default)]
+	pub format: Option<String>,
+	// output timezone like Asia/Shanghai, default UTC
+	#[serde(default)]
+	pub timezone: Option<String>,
+	// column name of the time message handled
+	#[serde(default)]
+	pub ingest_time: Option<String>,
+	// column name of the message timestamp from source like kafka
+	#[serde(default)]
+	pub source_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimestampColumn {
+	// flattened field
+	pub field: String,
+	// declared formats, empty is detect epoch seconds, epoch millis, rfc3339 and datetime
+	#[serde(default)]
+	pub formats: Vec<String>,
+	// timezone of value without offset, default UTC
+	#[serde(default)]
+	pub timezone: Option<String>,
+	#[serde(default)]
+	pub on_error: OnError,
+	#[serde(default)]
+	pub default: serde_json::Value,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeTarget {
+	EpochSeconds,
+	#[default]
+	EpochMillis,
+	Rfc3339,
+	// use format
+	Format,
+}
+
+fn parse_tz(tz: Option<&str>) -> anyhow::Result<Tz> {
+	match tz {
+		None => Ok(Tz::UTC),
+		Some(tz) => {
+			tz.parse::<Tz>().map_err(|err| anyhow::anyhow!("timezone {} error {}", tz, err))
+		}
+	}
+}
+
+impl TimestampConfig {
+	// columns with the time a message is handled, differ between deliveries of the same row
+	pub fn injected(&self) -> Vec<String> {
+		self.ingest_time.iter().chain(self.source_time.iter()).cloned().collect()
+	}
+}
+
+struct Column {
+	field: String,
+	formats: Vec<String>,
+	tz: Tz,
+	on_error: OnError,
+	default: serde_json::Value,
+}
+
+impl Column {
+	// parse value into epoch millis
+	fn parse(&self, val: &serde_json::Value) -> Option<i64> {
+		let s = match val {
+			serde_json::Value::Number(_) => return to_timestamp(val, None),
+			serde_json::Value::String(s) => s.trim(),
+			_ => return None,
+		};
+
+		if self.formats.is_empty() {
+			if s.parse::<i64>().is_ok() {
+				return to_timestamp(val, None);
+			}
+			if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+				return Some(dt.timestamp_millis());
+			}
+			return DETECT_FORMATS.iter().find_map(|format| self.parse_naive(s, format));
+		}
+
+		self.formats.iter().find_map(|format| match DateTime::parse_from_str(s, format) {
+			Ok(dt) => Some(dt.timestamp_millis()),
+			Err(_) => self.parse_naive(s, format),
+		})
+	}
+
+	// datetime without offset in column timezone
+	fn parse_naive(&self, s: &str, format: &str) -> Option<i64> {
+		let dt = NaiveDateTime::parse_from_str(s, format).ok()?;
+		self.tz.from_local_datetime(&dt).earliest().map(|dt| dt.timestamp_millis())
+	}
+}
+
+pub struct Timestamp {
+	columns: Vec<Column>,
+	target: TimeTarget,
+	format: String,
+	tz: Tz,
+	ingest_time: Option<String>,
+	source_time: Option<String>,
+}
+
+impl Timestamp {
+	pub fn new(conf: &TimestampConfig) -> anyhow::Result<Self> {
+		let format = match (conf.target, &conf.format) {
+			(TimeTarget::Format, None) => anyhow::bail!("timestamp target format need format"),
+			(_, format) => format.clone().unwrap_or_default(),
+		};
+		// bad spec fail when the row is formatted, check it once here
+		if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error)) {
+			anyhow::bail!("timestamp invalid format {}", format);
+		}
+
+		let columns = conf
+			.columns
+			.iter()
+			.map(|column| {
+				Ok(Column {
+					field: column.field.clone(),
+					formats: column.formats.clone(),
+					tz: parse_tz(column.timezone.as_deref())?,
+					on_error: column.on_error,
+					default: column.default.clone(),
+				})
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
+
+		Ok(Self {
+			columns,
+			target: conf.target,
+			format,
+			tz: parse_tz(conf.timezone.as_deref())?,
+			ingest_time: conf.ingest_time.clone(),
+			source_time: conf.source_time.clone(),
+		})
+	}
+
+	// epoch millis into target representation
+	fn output(&self, millis: i64) -> serde_json::Value {
+		match self.target {
+			TimeTarget::EpochSeconds => millis.div_euclid(1000).into(),
+			TimeTarget::EpochMillis => millis.into(),
+			TimeTarget::Rfc3339 | TimeTarget::Format => {
+				let Some(dt) = Utc.timestamp_millis_opt(millis).single() else {
+					return serde_json::Value::Null;
+				};
+				let dt = dt.with_timezone(&self.tz);
+				match self.target {
+					TimeTarget::Rfc3339 => dt.to_rfc3339().into(),
+					_ => dt.format(&self.format).to_string().into(),
+				}
+			}
+		}
+	}
+}
+
+impl Transform for Timestamp {
+	fn apply(&self, rows: &mut Vec<Row>, meta: &MsgMeta) -> anyhow::Result<()> {
+		let mut res = Vec::with_capacity(rows.len());
+		'row: for mut row in rows.drain(..) {
+			for column in self.columns.iter() {
+				let Some(val) = row.get_mut(&column.field) else {
+					continue;
+				};
+				// null keep null like output schema
+				if val.is_null() {
+					continue;
+				}
+				*val = match column.parse(val) {
+					Some(millis) => self.output(millis),
+					None => match column.on_error {
+						OnError::Null => serde_json::Value::Null,
+						OnError::Default => column.default.clone(),
+						OnError::DropRow => continue 'row,
+						OnError::Fail => {
+							anyhow::bail!("timestamp field {} can not parse {}", column.field, val)
+						}
+					},
+				};
+			}
+
+			if let Some(key) = &self.ingest_time {
+				row.insert(key.clone(), self.output(meta.ingest_time));
+			}
+			if let Some(key) = &self.source_time {
+				let val = meta.source_time.map_or(serde_json::Value::Null, |t| self.output(t));
+				row.insert(key.clone(), val);
+			}
+			res.push(row);
+		}
+		*rows = res;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use serde_json::json;
+
+	use lepumk::ani::Row;
+
+	use super::Timestamp;
+	use super::TimestampConfig;
+	use crate::biz::transform::MsgMeta;
+	use crate::biz::transform::Transform;
+
+	fn normalize(conf: serde_json::Value, rows: serde_json::Value) -> anyhow::Result<Vec<Row>> {
+		let conf: TimestampConfig = serde_json::from_value(conf)?;
+		let mut rows: Vec<Row> = serde_json::from_value(rows)?;
+		let meta = MsgMeta { ingest_time: 1704164645123, source_time: Some(1704164645000) };
+		Timestamp::new(&conf)?.apply(&mut rows, &meta)?;
+		Ok(rows)
+	}
+
+	#[test]
+	fn test_detect() -> anyhow::Result<()> {
+		let rows = normalize(
+			json!({"columns": [{"field": "ts"}], "ingest_time": "ingest", "source_time": "kafka"}),
+			json!([
+				{"ts": 1704164645},
+				{"ts": 1704164645123_i64},
+				{"ts": "2024-01-02T11:04:05+08:00"},
+				{"ts": "2024-01-02 03:04:05"},
+				{"ts": "1704164645"},
+				{"ts": null}
+			]),
+		)?;
+		let ts = rows.iter().map(|row| row["ts"].clone()).collect::<Vec<_>>();
+		assert_eq!(
+			json!(ts),
+			json!([
+				1704164645000_i64,
+				1704164645123_i64,
+				1704164645000_i64,
+				1704164645000_i64,
+				1704164645000_i64,
+				null
+			])
+		);
+		assert_eq!(rows[0]["ingest"], json!(1704164645123_i64));
+		assert_eq!(rows[0]["kafka"], json!(1704164645000_i64));
+		Ok(())
+	}
+
+	#[test]
+	fn test_format_and_timezone() -> anyhow::Result<()> {
+		let rows = normalize(
+			json!({
+				"columns": [{"field": "ts", "formats": ["%d/%m/%Y %H:%M"], "timezone": "Asia/Shanghai"}],
+				"target": "rfc3339",
+				"timezone": "UTC"
+			}),
+			json!([{"ts": "02/01/2024 11:04"}]),
+		)?;
+		assert_eq!(rows[0]["ts"], json!("2024-01-02T03:04:00+00:00"));
+
+		let rows = normalize(
+			json!({
+				"columns": [{"field": "ts"}],
+				"target": "format",
+				"format": "%Y-%m-%d %H:%M:%S",
+				"timezone": "Asia/Shanghai"
+			}),
+			json!([{"ts": 1704164645}]),
+		)?;
+		assert_eq!(rows[0]["ts"], json!("2024-01-02 11:04:05"));
+
+		let conf = json!({"columns": [{"field": "ts"}], "target": "format", "format": "%Q"});
+		assert!(Timestamp::new(&serde_json::from_value(conf)?).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_on_error() -> anyhow::Result<()> {
+		let conf = |on_error: &str| json!({"columns": [{"field": "ts", "on_error": on_error, "default": 0}], "target": "epoch_seconds"});
+		let rows = || json!([{"ts": "bad"}, {"ts": "2024-01-02 03:04:05"}]);
+
+		assert!(normalize(conf("null"), rows())?[0]["ts"].is_null());
+		assert_eq!(normalize(conf("default"), rows())?[0]["ts"], json!(0));
+		let res = normalize(conf("drop_row"), rows())?;
+		assert_eq!(res.len(), 1);
+		assert_eq!(res[0]["ts"], json!(1704164645));
+		assert!(normalize(conf("fail"), rows()).is_err());
+		assert!(normalize(json!({"target": "format"}), json!([])).is_err());
+		assert!(normalize(json!({"timezone": "Mars/Base"}), json!([])).is_err());
+		Ok(())
+	}
+}
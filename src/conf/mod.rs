@@ -11,6 +11,10 @@ pub struct AppConf {
 	pub http: HttpServer,
 	pub db: DBConf,
 	pub log: LogConfig,
+	#[serde(default)]
+	pub mask: MaskConf,
+	#[serde(default)]
+	pub dedup: DedupConf,
 }
 
 impl AppConf {
@@ -58,6 +62,32 @@ impl DBConf {
 	}
 }
 
+// mask transform config
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MaskConf {
+	// hmac secret of hash and tokenize, never put it in task config
+	#[serde(default)]
+	pub secret: String,
+}
+
+// dedup config
+#[derive(Debug, Deserialize, Clone)]
+pub struct DedupConf {
+	// dir of dedup snapshots, file name is the task id
+	#[serde(default = "default_snapshot_dir")]
+	pub snapshot_dir: String,
+}
+
+fn default_snapshot_dir() -> String {
+	"data/dedup".to_owned()
+}
+
+impl Default for DedupConf {
+	fn default() -> Self {
+		Self { snapshot_dir: default_snapshot_dir() }
+	}
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct LogConfig {
 	pub file_name: String,
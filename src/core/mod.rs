@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
 use anyhow::Context;
 
@@ -9,6 +12,10 @@ use serde::Serialize;
 
 use sqlx::MySqlPool;
 
+use tokio::sync::mpsc;
+
+use lepumk::ani::Row;
+
 use crate::conf;
 
 pub struct ServerContext {
@@ -18,6 +25,8 @@ pub struct ServerContext {
 
 impl ServerContext {
 	pub async fn new(conf: &conf::AppConf) -> anyhow::Result<Self> {
+		crate::biz::transform::mask::init_secret(&conf.mask);
+		crate::biz::dedup::init_snapshot_dir(&conf.dedup);
 		let app_state = AppState::new(conf)
 			.await
 			.with_context(|| format!("init database error {:?}", conf.db))?;
@@ -35,6 +44,7 @@ impl AppState {
 	pub async fn new(conf: &conf::AppConf) -> anyhow::Result<Self> {
 		// let db = init_db(&conf.db).await?;
 		let db_conn = crate::db::init_db_conn(&conf.db).await?;
+		crate::db::migrate(&db_conn).await?;
 		let data = Self {
 			//db: db,
 			db_conn,
@@ -106,11 +116,15 @@ impl IntoResponse for AppErr {
 	}
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct CoreMsg {
 	pub raw_msg: String,
 	pub raw_keys: HashSet<String>,
-	pub result: Vec<HashMap<String, serde_json::Value>>,
+	pub result: Vec<Row>,
+	// epoch millis message timestamp from source
+	pub timestamp: Option<i64>,
+	// copies of source message confirms, settled when the rows are sunk
+	pub acks: Vec<Ack>,
 }
 
 impl CoreMsg {
@@ -120,7 +134,7 @@ impl CoreMsg {
 }
 
 impl CoreMsg {
-	pub fn with_result(self, result: Vec<HashMap<String, serde_json::Value>>) -> Self {
+	pub fn with_result(self, result: Vec<Row>) -> Self {
 		Self { result, ..self }
 	}
 
@@ -131,12 +145,152 @@ impl CoreMsg {
 	pub fn with_raw_keys(self, row_keys: HashSet<String>) -> Self {
 		Self { raw_keys: row_keys, ..self }
 	}
+
+	pub fn with_timestamp(self, timestamp: Option<i64>) -> Self {
+		Self { timestamp, ..self }
+	}
+
+	pub fn with_ack(self, ack: Option<Ack>) -> Self {
+		Self { acks: ack.into_iter().collect(), ..self }
+	}
+
+	pub fn with_acks(self, acks: Vec<Ack>) -> Self {
+		Self { acks, ..self }
+	}
 }
 
 impl CoreMsg {
 	pub fn new(raw_msg: String) -> Self {
-		Self { raw_msg, result: vec![], raw_keys: HashSet::new() }
+		Self { raw_msg, result: vec![], raw_keys: HashSet::new(), timestamp: None, acks: vec![] }
+	}
+
+	// rows are sunk or dropped on purpose
+	pub fn done(self) {
+		self.acks.into_iter().for_each(Ack::done);
+	}
+
+	// rows can never be sunk
+	pub fn reject(self) {
+		self.acks.into_iter().for_each(Ack::reject);
 	}
 }
 
 unsafe impl Send for CoreMsg {}
+
+// how a source message is settled, the worst of its copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AckKind {
+	// every copy is sunk or dropped on purpose
+	Done = 0,
+	// the message can never be sunk like a parse error, not worth a redelivery
+	Reject = 1,
+	// a copy failed or was dropped unsettled, deliver it again
+	Retry = 2,
+}
+
+impl AckKind {
+	fn from_u8(kind: u8) -> Self {
+		match kind {
+			0 => AckKind::Done,
+			1 => AckKind::Reject,
+			_ => AckKind::Retry,
+		}
+	}
+}
+
+// id and result sent back to the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acked {
+	pub id: String,
+	pub kind: AckKind,
+}
+
+// copy of a source message confirm, every copy must be settled by done or reject,
+// a copy dropped without them like a failed write retries the message
+#[derive(Debug)]
+pub struct Ack {
+	id: Arc<AckId>,
+	settled: bool,
+}
+
+#[derive(Debug)]
+struct AckId {
+	id: String,
+	kind: AtomicU8,
+	sender: mpsc::UnboundedSender<Acked>,
+}
+
+impl Ack {
+	pub fn new(id: String, sender: mpsc::UnboundedSender<Acked>) -> Self {
+		let id = AckId { id, kind: AtomicU8::new(AckKind::Done as u8), sender };
+		Self { id: Arc::new(id), settled: false }
+	}
+
+	// this copy is sunk, or handed over to other copies
+	pub fn done(mut self) {
+		self.settled = true;
+	}
+
+	// this copy can never be sunk
+	pub fn reject(mut self) {
+		self.id.kind.fetch_max(AckKind::Reject as u8, Ordering::Relaxed);
+		self.settled = true;
+	}
+}
+
+impl Clone for Ack {
+	fn clone(&self) -> Self {
+		Self { id: self.id.clone(), settled: false }
+	}
+}
+
+impl Drop for Ack {
+	fn drop(&mut self) {
+		if !self.settled {
+			self.id.kind.fetch_max(AckKind::Retry as u8, Ordering::Relaxed);
+		}
+	}
+}
+
+impl Drop for AckId {
+	fn drop(&mut self) {
+		let kind = AckKind::from_u8(self.kind.load(Ordering::Relaxed));
+		// source stopped, the message is read again after restart
+		let _ = self.sender.send(Acked { id: std::mem::take(&mut self.id), kind });
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use tokio::sync::mpsc;
+
+	use super::Ack;
+	use super::AckKind;
+	use super::Acked;
+
+	#[test]
+	fn test_ack() {
+		let (s, mut r) = mpsc::unbounded_channel();
+		let acked = |id: &str, kind| Some(Acked { id: id.to_owned(), kind });
+
+		let ack = Ack::new("1".to_owned(), s.clone());
+		let copy = ack.clone();
+		ack.done();
+		assert!(r.try_recv().is_err());
+		copy.done();
+		assert_eq!(r.try_recv().ok(), acked("1", AckKind::Done));
+
+		// one copy dropped unsettled retry the whole message
+		let ack = Ack::new("2".to_owned(), s.clone());
+		let copy = ack.clone();
+		ack.done();
+		drop(copy);
+		assert_eq!(r.try_recv().ok(), acked("2", AckKind::Retry));
+
+		let ack = Ack::new("3".to_owned(), s);
+		let copy = ack.clone();
+		ack.reject();
+		copy.done();
+		assert_eq!(r.try_recv().ok(), acked("3", AckKind::Reject));
+	}
+}
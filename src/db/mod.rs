@@ -6,6 +6,7 @@ use sqlx::mysql::MySqlPoolOptions;
 use sqlx::MySqlPool;
 
 use tracing::debug;
+use tracing::info;
 use tracing::instrument;
 
 use crate::conf;
@@ -29,3 +30,33 @@ pub async fn init_db_conn(cfg: &conf::DBConf) -> anyhow::Result<MySqlPool> {
 
 	Ok(conn)
 }
+
+// task_info columns added after release, added on start if missing
+const TASK_INFO_COLUMNS: &[(&str, &str)] = &[
+	("handle_type_err", "bigint NOT NULL DEFAULT '0' COMMENT 'message with type coerce error'"),
+	("dedup_hit", "bigint NOT NULL DEFAULT '0' COMMENT 'duplicate row dropped'"),
+	("dedup_miss", "bigint NOT NULL DEFAULT '0' COMMENT 'row pass dedup'"),
+	("throttled", "bigint NOT NULL DEFAULT '0' COMMENT 'message wait for rate limit'"),
+	("sampled_out", "bigint NOT NULL DEFAULT '0' COMMENT 'row dropped by sample'"),
+];
+
+// add missing columns of existing tables, safe to run on every start
+#[instrument(skip(conn))]
+pub async fn migrate(conn: &MySqlPool) -> anyhow::Result<()> {
+	for (column, def) in TASK_INFO_COLUMNS {
+		let exists: i64 = sqlx::query_scalar(
+			"SELECT COUNT(*) FROM information_schema.COLUMNS \
+			 WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'task_info' AND COLUMN_NAME = ?",
+		)
+		.bind(column)
+		.fetch_one(conn)
+		.await?;
+		if exists > 0 {
+			continue;
+		}
+		let sql = format!("ALTER TABLE `task_info` ADD COLUMN `{}` {}", column, def);
+		info!("migrate {}", sql);
+		sqlx::query(&sql).execute(conn).await.with_context(|| format!("migrate {}", sql))?;
+	}
+	Ok(())
+}
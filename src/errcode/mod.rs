@@ -84,3 +84,13 @@ lazy_static! {
 	pub static ref DELETE_TASK_IS_DELETED_ERR: AppErr =
 		AppErr::new(40002, "task is already deleted");
 }
+
+lazy_static! {
+	pub static ref INGEST_TASK_NOT_RUNNING: AppErr =
+		AppErr::new(40100, "task not running or source is not http");
+	pub static ref INGEST_TASK_BUSY: AppErr = AppErr::new(40101, "task is busy, retry later");
+	pub static ref INGEST_BODY_ERR: AppErr =
+		AppErr::new(40102, "ingest body is not json or ndjson");
+	pub static ref INGEST_TOO_LARGE: AppErr =
+		AppErr::new(40103, "ingest records more than task buffer, split the request");
+}
@@ -0,0 +1,121 @@
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::routing::Router;
+
+use tracing::debug;
+use tracing::error;
+
+use crate::biz::link::source::http::ingest;
+use crate::biz::link::source::http::split_body;
+use crate::biz::link::source::http::IngestError;
+
+use crate::core::AppData;
+use crate::core::AppErr;
+use crate::errcode;
+use crate::extractor::RequestContext;
+
+use crate::types::IngestRequest;
+use crate::types::IngestResponse;
+
+pub struct IngestHandler;
+
+impl IngestHandler {
+	pub fn route<S: Clone + Send + Sync + 'static>() -> Router<S> {
+		Router::new().route("/ingest/:task_id", post(IngestHandler::ingest))
+	}
+}
+
+impl IngestHandler {
+	// push json document, json array or ndjson into running task with http source
+	#[tracing::instrument(skip(req_ctx, body))]
+	async fn ingest(
+		req_ctx: RequestContext,
+		Path(req): Path<IngestRequest>,
+		body: String,
+	) -> Result<AppData<IngestResponse>, (StatusCode, AppErr)> {
+		debug!("ingest task {} size {} uri {:?}", req.task_id, body.len(), req_ctx.uri);
+		let records = split_body(&body).map_err(|err| {
+			error!("ingest task {} body error {:?}", req.task_id, err);
+			(
+				StatusCode::BAD_REQUEST,
+				errcode::INGEST_BODY_ERR.clone().with_err_msg(err.to_string()),
+			)
+		})?;
+
+		match ingest(req.task_id, records) {
+			Ok(n) => Ok(AppData(n)),
+			Err(IngestError::NotRunning) => {
+				error!("ingest task {} not running with http source", req.task_id);
+				Err((StatusCode::NOT_FOUND, errcode::INGEST_TASK_NOT_RUNNING.clone()))
+			}
+			Err(IngestError::Full) => {
+				Err((StatusCode::TOO_MANY_REQUESTS, errcode::INGEST_TASK_BUSY.clone()))
+			}
+			Err(IngestError::TooLarge) => {
+				Err((StatusCode::PAYLOAD_TOO_LARGE, errcode::INGEST_TOO_LARGE.clone()))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashMap;
+
+	use axum::extract::Path;
+	use axum::http::HeaderMap;
+	use axum::http::Method;
+	use axum::http::StatusCode;
+	use axum::http::Uri;
+	use axum::http::Version;
+
+	use serde_json::json;
+
+	use tokio::sync::mpsc;
+
+	use super::IngestHandler;
+	use crate::biz::link::source::http::HttpSource;
+	use crate::biz::link::source::Source;
+	use crate::extractor::RequestContext;
+	use crate::types::IngestRequest;
+
+	async fn post(task_id: i64, body: &str) -> StatusCode {
+		let req_ctx = RequestContext {
+			method: Method::POST,
+			uri: Uri::from_static("/ingest"),
+			header: HeaderMap::new(),
+			version: Version::HTTP_11,
+			data: HashMap::new(),
+		};
+		let req = Path(IngestRequest { task_id });
+		match IngestHandler::ingest(req_ctx, req, body.to_owned()).await {
+			Ok(_) => StatusCode::OK,
+			Err((status, _)) => status,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_ingest_status() -> anyhow::Result<()> {
+		assert_eq!(post(8002, "{}").await, StatusCode::NOT_FOUND);
+
+		let source = HttpSource::new(&json!({"buffer": 2}))?;
+		source.bind(8002);
+		let (s, _r) = mpsc::channel(1);
+		let task = tokio::spawn(async move { source.source(s).await });
+		while post(8002, "").await != StatusCode::OK {
+			tokio::task::yield_now().await;
+		}
+
+		assert_eq!(post(8002, "bad").await, StatusCode::BAD_REQUEST);
+		assert_eq!(post(8002, "[1, 2, 3]").await, StatusCode::PAYLOAD_TOO_LARGE);
+		// without consumer the buffer get full
+		while post(8002, "[1, 2]").await == StatusCode::OK {
+			tokio::task::yield_now().await;
+		}
+		assert_eq!(post(8002, "[1, 2]").await, StatusCode::TOO_MANY_REQUESTS);
+
+		task.abort();
+		Ok(())
+	}
+}
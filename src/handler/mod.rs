@@ -1,5 +1,6 @@
 pub mod connect_handler;
 pub mod health;
+pub mod ingest;
 pub mod kafka_handler;
 pub mod metrics;
 pub mod parser;
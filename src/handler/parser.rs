@@ -7,8 +7,10 @@ use tracing::debug;
 use tracing::error;
 use tracing::instrument;
 
+use crate::biz::transform::MsgMeta;
 use crate::errcode;
 
+use crate::types::ParserDetailResponse;
 use crate::types::ParserPlainTextRequest;
 use crate::types::ParserPlainTextResponse;
 use crate::types::PropertyPlainTextRequest;
@@ -16,12 +18,13 @@ use crate::types::PropertyPlainTextResponse;
 
 use crate::core::AppData;
 use crate::core::AppErr;
+use crate::core::AppState;
 use crate::extractor::RequestContext;
 
 pub struct Parser;
 
 impl Parser {
-	pub fn route<S: Clone + Send + Sync + 'static>() -> Router<S> {
+	pub fn route() -> Router<AppState> {
 		Router::new()
 			.route("/parser", get("parser"))
 			.route("/debug/property", post(Parser::plain_text_property))
@@ -62,22 +65,124 @@ impl Parser {
 	) -> Result<AppData<ParserPlainTextResponse>, AppErr> {
 		debug!("parser plain text {:?} uri: {:?}", req, req_ctx.uri);
 
-		// build parser
-		let p = req.to_parser_json_parser();
+		// files and tables are only read by tasks, preview lookup with inline rows
+		if req.transform.lookup.iter().any(|lookup| !lookup.source.is_inline()) {
+			let msg = "debug parser lookup need inline rows".to_owned();
+			return Err(errcode::PARSER_ERROR.clone().with_err_msg(msg));
+		}
+		// build parser and transform
+		let p = match req.to_pipeline() {
+			Ok(p) => p,
+			Err(err) => {
+				error!("build pipeline error {:?}", err);
+				return Err(errcode::PARSER_ERROR.clone().with_err_msg(err.to_string()));
+			}
+		};
 		// parser
-		let res = p.run(&req.debug_str()).await;
+		let meta = MsgMeta::new(None);
+		let res = if req.explain {
+			p.explain(&req.debug_str(), &meta)
+		} else {
+			p.run(&req.debug_str(), &meta)
+		};
 		// process result
 		let res = match res {
-			Ok(res) => {
-				// info!("parser success");
-				Ok(res)
+			// bare rows unless detail asked
+			Ok(res) if req.detail || req.explain => {
+				Ok(ParserPlainTextResponse::Detail(ParserDetailResponse {
+					rows: res.rows,
+					depth_policy: req.depth_policy,
+					depth_exceeded: res.exceeded,
+					trace: req.explain.then_some(res.trace),
+				}))
 			}
+			Ok(res) => Ok(ParserPlainTextResponse::Rows(res.rows)),
 			Err(err) => {
 				error!("parser error text {} {:?}", &req.debug_str(), err);
-				Err(errcode::PARSER_ERROR.clone())
+				Err(errcode::PARSER_ERROR.clone().with_err_msg(err.to_string()))
 			}
 		};
 		// response parser result
 		crate::util::x_data(res)
 	}
 }
+
+#[cfg(test)]
+mod my_test {
+	use std::collections::HashMap;
+
+	use axum::extract::Json;
+	use axum::http::HeaderMap;
+	use axum::http::Method;
+	use axum::http::Uri;
+	use axum::http::Version;
+
+	use serde_json::json;
+
+	use super::Parser;
+	use crate::extractor::RequestContext;
+
+	fn req_ctx() -> RequestContext {
+		RequestContext {
+			method: Method::POST,
+			uri: Uri::from_static("/debug/parser"),
+			header: HeaderMap::new(),
+			version: Version::HTTP_11,
+			data: HashMap::new(),
+		}
+	}
+
+	fn debug_req() -> serde_json::Value {
+		json!({
+			"max_depth": 0,
+			"sep": ".",
+			"keys": [],
+			"ignore": [],
+			"fold": [],
+			"default_value": {},
+			"strict_mode": false,
+			"debug_text": {"user": {"id": 1}}
+		})
+	}
+
+	#[tokio::test]
+	async fn test_debug_parser_shape() {
+		// rows only by default
+		let res = Parser::parser(req_ctx(), Json(serde_json::from_value(debug_req()).unwrap()));
+		let res = serde_json::to_value(res.await.unwrap().0).unwrap();
+		assert_eq!(res, json!([{"user.id": 1}]));
+
+		let mut req = debug_req();
+		req["detail"] = json!(true);
+		let res = Parser::parser(req_ctx(), Json(serde_json::from_value(req).unwrap()));
+		let res = serde_json::to_value(res.await.unwrap().0).unwrap();
+		assert_eq!(res["rows"], json!([{"user.id": 1}]));
+		assert!(res.get("depth_policy").is_some());
+		assert!(res.get("trace").is_none());
+
+		let mut req = debug_req();
+		req["explain"] = json!(true);
+		let res = Parser::parser(req_ctx(), Json(serde_json::from_value(req).unwrap()));
+		let res = serde_json::to_value(res.await.unwrap().0).unwrap();
+		assert!(res["trace"].is_array());
+	}
+
+	#[tokio::test]
+	async fn test_debug_parser_lookup() {
+		let mut req = debug_req();
+		req["lookup"] = json!([{
+			"field": "user.id",
+			"source": {"type": "inline", "rows": [{"id": "1", "name": "alice"}]},
+			"key": "id",
+			"prefix": "user."
+		}]);
+		let res = Parser::parser(req_ctx(), Json(serde_json::from_value(req.clone()).unwrap()));
+		let res = serde_json::to_value(res.await.unwrap().0).unwrap();
+		assert_eq!(res, json!([{"user.id": 1, "user.name": "alice"}]));
+
+		// file and table of lookup are never read from a debug request
+		req["lookup"][0]["source"] = json!({"type": "csv", "path": "/etc/passwd"});
+		let res = Parser::parser(req_ctx(), Json(serde_json::from_value(req).unwrap())).await;
+		assert!(res.is_err());
+	}
+}
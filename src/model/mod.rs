@@ -1,2 +1,3 @@
 pub mod task;
+pub mod task_cursor;
 pub mod task_log;
@@ -17,6 +17,15 @@ use tracing::error;
 use tracing::info;
 use tracing::instrument;
 
+use lepumk::ani::schema::OutputSchema;
+use lepumk::ani::DepthPolicy;
+
+use crate::biz::aggregate::AggregateConfig;
+use crate::biz::dedup::DedupConfig;
+use crate::biz::throttle::RateLimitConfig;
+use crate::biz::throttle::SampleConfig;
+use crate::biz::transform::TransformConfig;
+
 use crate::core::AppErr;
 use crate::errcode;
 use crate::errcode::DB_INTERNAL_ERROR;
@@ -36,8 +45,13 @@ pub struct TaskInfo {
 	pub updated_at: i64,                  // task updated
 	pub property_item: serde_json::Value, // property item
 	// pub deleted_at: i64,                  // task deleted has value default is 0
-	pub handle_num: i64, // total handle message
-	pub handle_err: i64, // handler message error
+	pub handle_num: i64,      // total handle message
+	pub handle_err: i64,      // handler message error
+	pub handle_type_err: i64, // message with type coerce error
+	pub dedup_hit: i64,       // duplicate row dropped
+	pub dedup_miss: i64,      // row pass dedup
+	pub throttled: i64,       // message wait for rate limit
+	pub sampled_out: i64,     // row dropped by sample
 }
 
 impl TaskInfo {
@@ -95,6 +109,19 @@ pub struct ParserConfig {
 	pub fold: HashSet<String>,                             // fold value
 	pub default_value: HashMap<String, serde_json::Value>, // if value is null get  default value
 	pub strict_mode: bool,
+	pub schema: Option<OutputSchema>, // typed output schema
+	#[serde(default)]
+	pub depth_policy: DepthPolicy, // value deeper than max_depth fail, fold or truncate
+	#[serde(flatten)]
+	pub transform: TransformConfig, // transform after parser like mask
+	#[serde(default)]
+	pub dedup: Option<DedupConfig>, // drop duplicate row before sink
+	#[serde(default)]
+	pub aggregate: Option<AggregateConfig>, // window aggregate rows before sink
+	#[serde(default)]
+	pub rate_limit: Option<RateLimitConfig>, // max messages per second from source
+	#[serde(default)]
+	pub sample: Option<SampleConfig>, // keep rows by key hash ratio
 }
 
 impl TaskInfo {
@@ -404,14 +431,21 @@ impl TaskInfo {
 	}
 }
 
+// task counter increment since last flush
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TaskMeta {
+	pub handle_num: i64,
+	pub handle_err: i64,
+	pub handle_type_err: i64,
+	pub dedup_hit: i64,
+	pub dedup_miss: i64,
+	pub throttled: i64,
+	pub sampled_out: i64,
+}
+
 impl TaskInfo {
 	#[tracing::instrument(skip(conn))]
-	pub async fn update_meta(
-		conn: &MySqlPool,
-		id: i64,
-		add_handle_num: i64,
-		add_handle_err: i64,
-	) -> anyhow::Result<i64> {
+	pub async fn update_meta(conn: &MySqlPool, id: i64, meta: TaskMeta) -> anyhow::Result<i64> {
 		let heartbeat = chrono::Local::now().timestamp();
 		let updated_at = chrono::Local::now().timestamp();
 
@@ -420,11 +454,21 @@ impl TaskInfo {
 		 heartbeat = ?
 		 , handle_num = handle_num + ?
 		 , handle_err = handle_err + ?
+		 , handle_type_err = handle_type_err + ?
+		 , dedup_hit = dedup_hit + ?
+		 , dedup_miss = dedup_miss + ?
+		 , throttled = throttled + ?
+		 , sampled_out = sampled_out + ?
 		 ,updated_at = ? where id = ?"#,
 		)
 		.bind(heartbeat)
-		.bind(add_handle_num)
-		.bind(add_handle_err)
+		.bind(meta.handle_num)
+		.bind(meta.handle_err)
+		.bind(meta.handle_type_err)
+		.bind(meta.dedup_hit)
+		.bind(meta.dedup_miss)
+		.bind(meta.throttled)
+		.bind(meta.sampled_out)
 		.bind(updated_at)
 		.bind(id)
 		.execute(conn)
@@ -0,0 +1,43 @@
+use anyhow::Context;
+
+use sqlx::FromRow;
+use sqlx::MySql;
+use sqlx::MySqlPool;
+
+use tracing::debug;
+
+// source position of a task, restart continue from it
+#[derive(Debug, FromRow)]
+pub struct TaskCursor {
+	pub task_id: i64,     // task id
+	pub position: String, // source position like page cursor or binlog position
+	pub updated_at: i64,  // updated cursor time
+}
+
+impl TaskCursor {
+	pub async fn fetch(conn: &MySqlPool, task_id: i64) -> anyhow::Result<Option<String>> {
+		let res =
+			sqlx::query_as::<MySql, TaskCursor>(r#"select * from task_cursor where task_id = ?"#)
+				.bind(task_id)
+				.fetch_optional(conn)
+				.await
+				.with_context(|| format!("fetch task_cursor where task_id = {}", task_id))?;
+		Ok(res.map(|c| c.position))
+	}
+
+	pub async fn save(conn: &MySqlPool, task_id: i64, position: &str) -> anyhow::Result<()> {
+		debug!("save task {} position {}", task_id, position);
+		let updated_at = chrono::Local::now().timestamp();
+		sqlx::query(
+			r#"insert into task_cursor (task_id, position, updated_at) values (?, ?, ?)
+		 on duplicate key update position = values(position), updated_at = values(updated_at)"#,
+		)
+		.bind(task_id)
+		.bind(position)
+		.bind(updated_at)
+		.execute(conn)
+		.await
+		.with_context(|| format!("save task_cursor task_id = {}", task_id))?;
+		Ok(())
+	}
+}
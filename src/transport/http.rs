@@ -20,6 +20,7 @@ use tracing::Level;
 use crate::core::ServerContext;
 use crate::handler::fallback_handler;
 use crate::handler::health::HealthHandler;
+use crate::handler::ingest::IngestHandler;
 use crate::handler::kafka_handler::KafkaHandler;
 use crate::handler::metrics::MetricsHandler;
 use crate::handler::parser::Parser;
@@ -39,6 +40,7 @@ pub async fn start(srv_ctx: &ServerContext) -> anyhow::Result<()> {
 	let app = Router::new()
 		.merge(HealthHandler::route())
 		.merge(Parser::route())
+		.merge(IngestHandler::route())
 		.route("/task/:id", get(TaskHandler::fetch_task))
 		.route("/task", post(TaskHandler::create_task))
 		.route("/task", put(TaskHandler::update_task))
@@ -1,4 +1,13 @@
 use lepumk::ani;
+use lepumk::ani::schema::OutputSchema;
+use lepumk::ani::DepthPolicy;
+
+use crate::biz::aggregate::AggregateConfig;
+use crate::biz::dedup::DedupConfig;
+use crate::biz::throttle::RateLimitConfig;
+use crate::biz::throttle::SampleConfig;
+use crate::biz::transform::Pipeline;
+use crate::biz::transform::TransformConfig;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -60,7 +69,16 @@ pub struct ParserPlainTextRequest {
 	pub fold: HashSet<String>,                             // fold value
 	pub default_value: HashMap<String, serde_json::Value>, // if value is null get  default value
 	pub strict_mode: bool,                                 // run in strict mode or not
+	pub schema: Option<OutputSchema>,                      // typed output schema
+	#[serde(default)]
+	pub depth_policy: DepthPolicy,       // value deeper than max_depth fail, fold or truncate
+	#[serde(flatten)]
+	pub transform: TransformConfig,      // transform after parser like mask
 	pub debug_text: serde_json::Value,                     // demo text
+	#[serde(default)]
+	pub explain: bool,                   // return the decision of every key
+	#[serde(default)]
+	pub detail: bool,                    // return rows with depth policy, implied by explain
 }
 
 impl ParserPlainTextRequest {
@@ -97,12 +115,36 @@ impl ParserPlainTextRequest {
 			.with_max_depth(self.max_depth)
 			.with_keys(self.get_keys())
 			.with_default_value(self.default_value.clone())
+			.with_schema(self.schema.clone())
+			.with_depth_policy(self.depth_policy)
 			.init()
 	}
+
+	pub fn to_pipeline(&self) -> anyhow::Result<Pipeline> {
+		self.transform.build(self.to_parser_json_parser())
+	}
+}
+
+// parser plain text response contain every parser item
+// and the detail of them with detail or explain
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ParserPlainTextResponse {
+	Rows(Vec<ani::Row>),
+	Detail(ParserDetailResponse),
 }
 
-// parser plain text response is a vector that contain every parser item
-pub type ParserPlainTextResponse = Vec<HashMap<String, serde_json::Value>>;
+// every parser item, the subtree over max_depth which is fold or truncate
+// and trace of every key in explain mode
+#[derive(Debug, Serialize)]
+pub struct ParserDetailResponse {
+	pub rows: Vec<ani::Row>,
+	pub depth_policy: DepthPolicy,
+	pub depth_exceeded: Vec<ani::DepthExceeded>,
+	// decision of every key with explain
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub trace: Option<Vec<ani::trace::Trace>>,
+}
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct FetchTaskRequest {
@@ -237,6 +279,19 @@ pub struct JsonParserOpt {
 	pub fold: HashSet<String>,                             // fold value
 	pub default_value: HashMap<String, serde_json::Value>, // if value is null get  default value
 	pub strict_mode: bool,                                 // run in strict mode or not
+	pub schema: Option<OutputSchema>,                      // typed output schema
+	#[serde(default)]
+	pub depth_policy: DepthPolicy,       // value deeper than max_depth fail, fold or truncate
+	#[serde(flatten)]
+	pub transform: TransformConfig,      // transform after parser like mask
+	#[serde(default)]
+	pub dedup: Option<DedupConfig>,      // drop duplicate row before sink
+	#[serde(default)]
+	pub aggregate: Option<AggregateConfig>, // window aggregate rows before sink
+	#[serde(default)]
+	pub rate_limit: Option<RateLimitConfig>, // max messages per second from source
+	#[serde(default)]
+	pub sample: Option<SampleConfig>,    // keep rows by key hash ratio
 }
 
 impl JsonParserOpt {
@@ -249,8 +304,14 @@ impl JsonParserOpt {
 			.with_max_depth(self.max_depth)
 			.with_keys(self.get_keys())
 			.with_default_value(self.default_value.clone())
+			.with_schema(self.schema.clone())
+			.with_depth_policy(self.depth_policy)
 			.init()
 	}
+
+	pub fn to_pipeline(&self) -> anyhow::Result<Pipeline> {
+		self.transform.build(self.to_parser())
+	}
 }
 
 impl JsonParserOpt {
@@ -277,6 +338,14 @@ pub struct StartTaskRequest {
 
 pub type StartTaskResponse = ();
 
+#[derive(Debug, Deserialize)]
+pub struct IngestRequest {
+	pub task_id: i64,
+}
+
+// accepted records
+pub type IngestResponse = usize;
+
 #[derive(Debug, Deserialize)]
 pub struct StopTaskRequest {
 	pub id: i64,
@@ -1,4 +1,5 @@
 use std::any::type_name;
+use std::borrow::Cow;
 use std::fmt::Debug;
 
 use anyhow::Context;
@@ -8,9 +9,14 @@ use serde::Serialize;
 
 use serde_json::json;
 
+use sha2::Digest;
+use sha2::Sha256;
+
 use tracing::error;
 use tracing::trace;
 
+use lepumk::ani::Row;
+
 use crate::core::AppData;
 use crate::core::AppErr;
 
@@ -32,3 +38,28 @@ pub fn x_data<T: Serialize + Debug>(data: Result<T, AppErr>) -> Result<AppData<T
 		}
 	}
 }
+
+// sha-256 of key fields, or the whole row without skip fields like injected ingest time,
+// stable between process, not std hasher
+pub fn row_digest(row: &Row, keys: &[String], skip: &[String]) -> [u8; 32] {
+	let mut hasher = Sha256::new();
+	if keys.is_empty() {
+		let row = match skip.iter().any(|key| row.contains_key(key)) {
+			true => Cow::Owned(
+				row.iter()
+					.filter(|(key, _)| !skip.contains(key))
+					.map(|(key, val)| (key.clone(), val.clone()))
+					.collect::<Row>(),
+			),
+			false => Cow::Borrowed(row),
+		};
+		hasher.update(json!(row).to_string());
+	} else {
+		let vals = keys
+			.iter()
+			.map(|key| row.get(key).unwrap_or(&serde_json::Value::Null))
+			.collect::<Vec<_>>();
+		hasher.update(json!(vals).to_string());
+	}
+	hasher.finalize().into()
+}